@@ -2,18 +2,69 @@ extern crate clap;
 
 use self::clap::*;
 
+use command::config::ConfigRunner;
+use command::group::GroupRunner;
+use command::health::HealthRunner;
 use command::list::ListRunner;
+use command::logs::LogsRunner;
 use command::register::RegisterRunner;
+use command::restart::RestartRunner;
 use command::start::StartRunner;
 use command::stop::StopRunner;
+use command::supervise::SuperviseRunner;
+use command::top::TopRunner;
 use command::Subcommand;
 
 pub fn new() -> App<'static, 'static> {
     app_from_crate!()
         .setting(AppSettings::VersionlessSubcommands)
         .setting(AppSettings::SubcommandRequired)
+        .arg(
+            Arg::with_name("home")
+                .help("dir for the registry, runfiles and logs [env: DMGR_HOME]")
+                .long("home")
+                .value_name("DIR")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("registry")
+                .help("service registry to use [env: DMGR_REGISTRY]")
+                .long("registry")
+                .value_name("FILE")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("log_dir")
+                .help("dir for service logs [env: DMGR_LOG_DIR]")
+                .long("log_dir")
+                .value_name("DIR")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("color")
+                .help("when to color output [env: DMGR_COLOR]")
+                .long("color")
+                .value_name("WHEN")
+                .possible_values(&["auto", "always", "never"])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("log_level")
+                .help("least severe messages to log [env: DMGR_LOG_LEVEL]")
+                .long("log_level")
+                .value_name("LEVEL")
+                .possible_values(&["off", "error", "warn", "info", "debug", "trace"])
+                .global(true),
+        )
         .subcommand(ListRunner::sub_cmd())
         .subcommand(StartRunner::sub_cmd())
         .subcommand(RegisterRunner::sub_cmd())
         .subcommand(StopRunner::sub_cmd())
+        .subcommand(RestartRunner::sub_cmd())
+        .subcommand(GroupRunner::sub_cmd())
+        .subcommand(LogsRunner::sub_cmd())
+        .subcommand(HealthRunner::sub_cmd())
+        .subcommand(TopRunner::sub_cmd())
+        .subcommand(ConfigRunner::sub_cmd())
+        .subcommand(SuperviseRunner::sub_cmd())
 }
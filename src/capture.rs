@@ -0,0 +1,185 @@
+// Captures service output line by line, tagging each line with a timestamp
+// and the stream it was written to
+extern crate chrono;
+extern crate serde_derive;
+extern crate serde_json;
+
+use self::chrono::{DateTime, Local};
+use self::serde_derive::{Deserialize, Serialize};
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+use command::DmgrResult;
+use config::CaptureMode;
+use process;
+
+pub const STDOUT: &str = "out";
+pub const STDERR: &str = "err";
+
+// RFC 3339 with milliseconds, so captured lines sort and filter by time
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f%:z";
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CapturedLine {
+    pub ts: String,
+    pub stream: String,
+    pub line: String,
+}
+
+impl CapturedLine {
+    pub fn timestamp(&self) -> Option<DateTime<Local>> {
+        DateTime::parse_from_rfc3339(&self.ts)
+            .ok()
+            .map(|dt| dt.with_timezone(&Local))
+    }
+
+    // The text capture format, e.g. "2019-03-01T12:00:00.000+00:00 err | oops"
+    pub fn render(&self) -> String {
+        format!("{} {} | {}", self.ts, self.stream, self.line)
+    }
+}
+
+pub fn format_line(mode: CaptureMode, stream: &str, line: &str) -> DmgrResult<String> {
+    let captured = CapturedLine {
+        ts: Local::now().format(TIMESTAMP_FORMAT).to_string(),
+        stream: stream.to_string(),
+        line: line.to_string(),
+    };
+
+    match mode {
+        CaptureMode::Raw => Ok(captured.line),
+        CaptureMode::Text => Ok(captured.render()),
+        CaptureMode::Json => Ok(serde_json::to_string(&captured)?),
+    }
+}
+
+// Reads back a line written in either the text or JSON capture format. Other
+// lines, e.g. from services started without capture, give None.
+pub fn parse_line(line: &str) -> Option<CapturedLine> {
+    if line.starts_with('{') {
+        return serde_json::from_str(line).ok();
+    }
+
+    let mut parts = line.splitn(3, ' ');
+    let ts = parts.next()?;
+    let stream = parts.next()?;
+    let rest = parts.next()?;
+
+    if (stream != STDOUT && stream != STDERR) || !rest.starts_with("| ") {
+        return None;
+    }
+    DateTime::parse_from_rfc3339(ts).ok()?;
+
+    Some(CapturedLine {
+        ts: ts.to_string(),
+        stream: stream.to_string(),
+        line: rest[2..].to_string(),
+    })
+}
+
+// Runs the command with its stdout and stderr piped through this process into
+// the log, and returns once both pipes are closed and the command has exited
+pub fn supervise(mut cmd: Command, log: &PathBuf, mode: CaptureMode) -> DmgrResult<ExitStatus> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // stopping the service signals the whole group; the command decides how
+    // to handle that, and the supervisor keeps copying until it has exited
+    process::ignore_termination();
+
+    let log = Arc::new(Mutex::new(
+        OpenOptions::new().create(true).append(true).open(log)?,
+    ));
+
+    let mut copiers = vec![];
+    if let Some(out) = child.stdout.take() {
+        copiers.push(copy_lines(out, STDOUT, mode, log.clone()));
+    }
+    if let Some(err) = child.stderr.take() {
+        copiers.push(copy_lines(err, STDERR, mode, log.clone()));
+    }
+
+    for copier in copiers {
+        copier
+            .join()
+            .map_err(|_| dmgr_err!("output capture thread panicked"))??;
+    }
+
+    Ok(child.wait()?)
+}
+
+fn copy_lines<R: Read + Send + 'static>(
+    stream: R,
+    tag: &'static str,
+    mode: CaptureMode,
+    log: Arc<Mutex<File>>,
+) -> JoinHandle<DmgrResult> {
+    thread::spawn(move || {
+        for line in BufReader::new(stream).split(b'\n') {
+            let line = line?;
+            let text = String::from_utf8_lossy(&line);
+            let mut formatted = format_line(mode, tag, text.trim_end_matches('\r'))?;
+            formatted.push('\n');
+
+            // one write per line, so lines from the two streams never interleave
+            let mut file = log
+                .lock()
+                .map_err(|_| dmgr_err!("log file lock poisoned"))?;
+            file.write_all(formatted.as_bytes())?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        for &mode in &[CaptureMode::Text, CaptureMode::Json] {
+            let written = format_line(mode, STDERR, "oops | again").unwrap();
+            let captured = parse_line(&written).unwrap();
+            assert_eq!(captured.stream, STDERR);
+            assert_eq!(captured.line, "oops | again");
+            assert!(captured.timestamp().is_some());
+        }
+    }
+
+    #[test]
+    fn reads_text_lines() {
+        let captured = parse_line("2019-03-01T12:00:00.000+00:00 out | ").unwrap();
+        assert_eq!(captured.ts, "2019-03-01T12:00:00.000+00:00");
+        assert_eq!(captured.stream, STDOUT);
+        assert_eq!(captured.line, "");
+    }
+
+    #[test]
+    fn ignores_uncaptured_lines() {
+        for line in &[
+            "",
+            "plain output",
+            format_line(CaptureMode::Raw, STDOUT, "raw")
+                .unwrap()
+                .as_str(),
+            "2019-03-01T12:00:00.000+00:00 log | wrong stream",
+            "2019-03-01T12:00:00.000+00:00 out no separator",
+            "2019-03-01 12:00:00 out | not rfc 3339",
+            r#"{"level": "info", "msg": "structured, but not captured"}"#,
+        ] {
+            assert!(parse_line(line).is_none(), "{:?}", line);
+        }
+    }
+}
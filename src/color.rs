@@ -0,0 +1,80 @@
+// ANSI colors for console output
+extern crate libc;
+extern crate serde_derive;
+
+use self::serde_derive::Deserialize;
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use command::DmgrErr;
+
+pub const RED: u8 = 31;
+pub const GREEN: u8 = 32;
+pub const YELLOW: u8 = 33;
+pub const BLUE: u8 = 34;
+pub const MAGENTA: u8 = 35;
+pub const CYAN: u8 = 36;
+
+// Colors handed out in turn, e.g. one per service in interleaved output
+pub const PALETTE: [u8; 6] = [CYAN, YELLOW, GREEN, MAGENTA, BLUE, RED];
+
+// Auto only colors output going to a terminal, so piped output stays clean
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ColorMode::Auto => "auto",
+            ColorMode::Always => "always",
+            ColorMode::Never => "never",
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = DmgrErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => err!("unknown color mode {:?}; use auto, always or never", s),
+        }
+    }
+}
+
+// Set once from the settings, as painting happens far too often to resolve
+// them each time
+static MODE: AtomicUsize = AtomicUsize::new(ColorMode::Auto as usize);
+
+pub fn set_mode(mode: ColorMode) {
+    MODE.store(mode as usize, Ordering::Relaxed)
+}
+
+pub fn enabled() -> bool {
+    match MODE.load(Ordering::Relaxed) {
+        m if m == ColorMode::Always as usize => true,
+        m if m == ColorMode::Never as usize => false,
+        _ => unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 },
+    }
+}
+
+pub fn paint(s: &str, color: u8) -> String {
+    if enabled() {
+        format!("\x1b[{}m{}\x1b[0m", color, s)
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn cycle(i: usize) -> u8 {
+    PALETTE[i % PALETTE.len()]
+}
@@ -0,0 +1,84 @@
+extern crate serde_derive;
+extern crate serde_json;
+
+use self::serde_derive::Serialize;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use command::DmgrResult;
+use command::{Runnable, Subcommand};
+use settings::Settings;
+use table::TableBuilder;
+
+#[derive(Debug)]
+pub struct ConfigRunner<'a> {
+    pub args: &'a ArgMatches<'a>,
+}
+
+impl<'a> Subcommand for ConfigRunner<'a> {
+    const NAME: &'static str = "config";
+
+    fn sub_cmd() -> App<'static, 'static> {
+        SubCommand::with_name(Self::NAME)
+            .about("inspects dmgr's settings")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("show")
+                    .about("shows each setting in effect and where it came from")
+                    .arg(
+                        Arg::with_name("json")
+                            .help("print the settings as JSON")
+                            .long("json"),
+                    ),
+            )
+    }
+}
+
+impl<'a> Runnable<'a> for ConfigRunner<'a> {
+    fn new(args: &'a ArgMatches) -> Self {
+        ConfigRunner { args }
+    }
+
+    fn run(&self) -> DmgrResult {
+        match self.args.subcommand() {
+            ("show", Some(args)) => show(args),
+            _ => fail!("unknown config subcommand"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SettingOutput {
+    name: &'static str,
+    value: String,
+    source: String,
+}
+
+fn show(args: &ArgMatches) -> DmgrResult {
+    let settings = Settings::get()?;
+    let described = settings.describe();
+
+    if args.is_present("json") {
+        let out: Vec<SettingOutput> = described
+            .into_iter()
+            .map(|(name, value, source)| SettingOutput {
+                name,
+                value,
+                source: source.to_string(),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    described
+        .into_iter()
+        .map(|(name, value, source)| vec![name.to_string(), value, source.to_string()])
+        .fold(
+            TableBuilder::new().header(vec!["Setting", "Value", "Source"]),
+            |t, r| t.add_row(r),
+        )
+        .build()
+        .printstd();
+
+    Ok(())
+}
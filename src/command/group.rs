@@ -0,0 +1,130 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::info;
+
+use command::DmgrResult;
+use command::{Runnable, Subcommand};
+use config::ServiceRegistry;
+use table::TableBuilder;
+
+#[derive(Debug)]
+pub struct GroupRunner<'a> {
+    pub args: &'a ArgMatches<'a>,
+}
+
+impl<'a> Subcommand for GroupRunner<'a> {
+    const NAME: &'static str = "group";
+
+    fn sub_cmd() -> App<'static, 'static> {
+        SubCommand::with_name(Self::NAME)
+            .about("manages groups of services")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("add")
+                    .about("add services to a group, creating it if necessary")
+                    .arg(
+                        Arg::with_name("group")
+                            .help("group to add to")
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::with_name("services")
+                            .help("registered services to add")
+                            .required(true)
+                            .multiple(true),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("remove")
+                    .about("remove services from a group")
+                    .alias("rm")
+                    .arg(
+                        Arg::with_name("group")
+                            .help("group to remove from")
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::with_name("services")
+                            .help("services to remove; when empty, removes the whole group")
+                            .multiple(true),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("list")
+                    .about("lists groups and their services")
+                    .alias("ls")
+                    .arg(Arg::with_name("group").help("only list this group")),
+            )
+    }
+}
+
+impl<'a> Runnable<'a> for GroupRunner<'a> {
+    fn new(args: &'a ArgMatches) -> Self {
+        GroupRunner { args }
+    }
+
+    fn run(&self) -> DmgrResult {
+        match self.args.subcommand() {
+            ("add", Some(args)) => add(args),
+            ("remove", Some(args)) => remove(args),
+            ("list", Some(args)) => list(args),
+            _ => fail!("unknown group subcommand"),
+        }
+    }
+}
+
+fn add<'a>(args: &'a ArgMatches) -> DmgrResult {
+    let group = args.value_of("group").unwrap();
+    let services: Vec<&str> = args.values_of("services").unwrap().collect();
+
+    ServiceRegistry::get()?
+        .add_to_group(group, &services)?
+        .save()?;
+    info!("added {:?} to group {:?}", services, group);
+
+    Ok(())
+}
+
+fn remove<'a>(args: &'a ArgMatches) -> DmgrResult {
+    let group = args.value_of("group").unwrap();
+    let services: Vec<&str> = args
+        .values_of("services")
+        .map(|v| v.collect())
+        .unwrap_or_default();
+
+    ServiceRegistry::get()?
+        .remove_from_group(group, &services)?
+        .save()?;
+
+    if services.is_empty() {
+        info!("removed group {:?}", group);
+    } else {
+        info!("removed {:?} from group {:?}", services, group);
+    }
+
+    Ok(())
+}
+
+fn list<'a>(args: &'a ArgMatches) -> DmgrResult {
+    let registry = ServiceRegistry::get()?;
+    let only = args.value_of("group");
+
+    if let Some(group) = only {
+        if !registry.groups.contains_key(group) {
+            fail!("unable to find group {:?}", group)
+        }
+    }
+
+    let t = TableBuilder::new().header(vec!["Group", "Services"]);
+
+    registry
+        .groups
+        .iter()
+        .filter(|&(name, _)| only.map_or(true, |g| g == name))
+        .fold(t, |t, (name, members)| {
+            t.add_row(vec![name.clone(), members.join(", ")])
+        })
+        .build()
+        .printstd();
+
+    Ok(())
+}
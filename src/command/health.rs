@@ -0,0 +1,125 @@
+extern crate serde_derive;
+extern crate serde_json;
+
+use self::serde_derive::Serialize;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use color;
+use command::DmgrErr;
+use command::DmgrResult;
+use command::{Runnable, Subcommand};
+use config::ServiceRegistry;
+use health;
+use health::ServiceReport;
+use table::TableBuilder;
+
+#[derive(Debug)]
+pub struct HealthRunner<'a> {
+    pub args: &'a ArgMatches<'a>,
+}
+
+impl<'a> Subcommand for HealthRunner<'a> {
+    const NAME: &'static str = "health";
+
+    fn sub_cmd() -> App<'static, 'static> {
+        SubCommand::with_name(Self::NAME)
+            .about("run the readiness checks of services or groups, failing if any don't pass")
+            .arg(
+                Arg::with_name("service_or_group")
+                    .help("services or groups to check")
+                    .multiple(true)
+                    .required_unless("all")
+                    .conflicts_with("all"),
+            )
+            .arg(
+                Arg::with_name("all")
+                    .help("check all registered services")
+                    .long("all")
+                    .short("a")
+                    .conflicts_with("service_or_group"),
+            )
+            .arg(
+                Arg::with_name("json")
+                    .help("print the results as JSON")
+                    .long("json"),
+            )
+    }
+}
+
+impl<'a> Runnable<'a> for HealthRunner<'a> {
+    fn new(args: &'a ArgMatches) -> Self {
+        HealthRunner { args }
+    }
+
+    fn run(&self) -> DmgrResult {
+        let registry = ServiceRegistry::get()?;
+
+        let services = match self.args.values_of("service_or_group") {
+            Some(targets) => {
+                let mut services = vec![];
+                for name in &registry.resolve_all(targets)? {
+                    services.push(registry.get_service(name)?);
+                }
+                services
+            }
+            None => registry.loadable_services(),
+        };
+
+        let reports: Vec<ServiceReport> = services.iter().map(health::report).collect();
+        let unhealthy: Vec<&String> = reports
+            .iter()
+            .filter(|r| !r.healthy)
+            .map(|r| &r.name)
+            .collect();
+
+        if self.args.is_present("json") {
+            let out = HealthOutput {
+                healthy: unhealthy.is_empty(),
+                services: &reports,
+            };
+            println!("{}", serde_json::to_string_pretty(&out)?);
+
+            // the JSON already says which services are unhealthy
+            if !unhealthy.is_empty() {
+                return Err(DmgrErr::silent(&format!("unhealthy: {:?}", unhealthy)));
+            }
+            return Ok(());
+        }
+
+        print_table(&reports);
+
+        if unhealthy.is_empty() {
+            Ok(())
+        } else {
+            fail!("unhealthy: {:?}", unhealthy)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HealthOutput<'a> {
+    healthy: bool,
+    services: &'a [ServiceReport],
+}
+
+fn print_table(reports: &[ServiceReport]) {
+    let mut t = TableBuilder::new().header(vec!["Service", "Check", "Result", "Latency"]);
+
+    for report in reports {
+        for check in &report.checks {
+            let result = match check.error {
+                Some(ref e) => color::paint(&format!("failed: {}", e), color::RED),
+                None => color::paint("ok", color::GREEN),
+            };
+            t = t.add_row(vec![
+                report.name.clone(),
+                check.check.clone(),
+                result,
+                format!("{}ms", check.latency_ms),
+            ]);
+        }
+    }
+
+    t.build().printstd();
+}
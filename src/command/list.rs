@@ -1,11 +1,34 @@
+extern crate glob;
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
+
+use self::glob::Pattern;
+use self::serde_derive::Serialize;
+use self::serde_json::Value;
 use clap::{App, Arg, ArgMatches, SubCommand};
-use prettytable::format;
-use prettytable::Table;
-use prettytable::*;
+use sysinfo::SystemExt;
 
 use command::DmgrResult;
 use command::{Runnable, Subcommand};
+use config::Pid;
 use config::ServiceRegistry;
+use config::StartMode;
+use constants;
+use process;
+use service::Service;
+use settings::Settings;
+use status;
+use status::Snapshot;
+use status::State;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+use table::TableBuilder;
 
 #[derive(Debug)]
 pub struct ListRunner<'a> {
@@ -19,9 +42,98 @@ impl<'a> Subcommand for ListRunner<'a> {
         SubCommand::with_name(Self::NAME)
             .about("lists services")
             .alias("ls")
-            // TODO: are these necessary?
-            .arg(Arg::with_name("all").long("all").short("a"))
-            .arg(Arg::with_name("hidden only").long("hidden").short("h"))
+            .arg(
+                Arg::with_name("all")
+                    .help("include hidden services")
+                    .long("all")
+                    .short("a")
+                    .conflicts_with("hidden"),
+            )
+            .arg(
+                Arg::with_name("hidden")
+                    .help("only list hidden services")
+                    .long("hidden")
+                    .short("h"),
+            )
+            .arg(
+                Arg::with_name("status")
+                    .help("only list services with one of these statuses")
+                    .long("status")
+                    .short("s")
+                    .value_name("STATUS")
+                    .use_delimiter(true)
+                    .possible_values(&Filter::STATUSES),
+            )
+            .arg(
+                Arg::with_name("group")
+                    .help("only list services in one of these groups")
+                    .long("group")
+                    .short("g")
+                    .value_name("GROUP")
+                    .use_delimiter(true),
+            )
+            .arg(
+                Arg::with_name("tag")
+                    .help("only list services with one of these tags")
+                    .long("tag")
+                    .short("t")
+                    .value_name("TAG")
+                    .use_delimiter(true),
+            )
+            .arg(
+                Arg::with_name("name")
+                    .help("only list services whose name matches a glob, e.g. 'api-*'")
+                    .long("name")
+                    .value_name("GLOB"),
+            )
+            .arg(
+                Arg::with_name("sort")
+                    .help("order services by a key; uptime and mem put the largest first")
+                    .long("sort")
+                    .value_name("KEY")
+                    .possible_values(&["name", "status", "uptime", "mem"])
+                    .default_value("name"),
+            )
+            .arg(
+                Arg::with_name("timeout")
+                    .help(
+                        "milliseconds to wait for a service's status before showing it as unknown",
+                    )
+                    .long("timeout")
+                    .value_name("MILLIS")
+                    .default_value(constants::DEFAULT_STATUS_TIMEOUT_MILLIS),
+            )
+            .arg(
+                Arg::with_name("columns")
+                    .help("columns to show after the service name; cpu takes a moment to sample")
+                    .long("columns")
+                    .short("c")
+                    .value_name("COLUMNS")
+                    .use_delimiter(true)
+                    .possible_values(&Column::NAMES)
+                    .conflicts_with("wide"),
+            )
+            .arg(
+                Arg::with_name("wide")
+                    .help("show all columns")
+                    .long("wide")
+                    .short("w"),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .help("print as a table, or as JSON, YAML or tab-separated values")
+                    .long("output")
+                    .short("o")
+                    .value_name("FORMAT")
+                    .possible_values(&["table", "json", "yaml", "tsv"])
+                    .conflicts_with("format"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .help("print each service with a template, e.g. '{name}\\t{status}\\t{open_ports}'")
+                    .long("format")
+                    .value_name("TEMPLATE"),
+            )
     }
 }
 
@@ -30,64 +142,470 @@ impl<'a> Runnable<'a> for ListRunner<'a> {
         ListRunner { args }
     }
     fn run(&self) -> DmgrResult {
-        const SVC_REG: &str = "/Users/tkbrigham/.solo/service-registry.json";
-        let reg = ServiceRegistry::from(SVC_REG)?;
+        let reg = ServiceRegistry::get()?;
+
+        let columns = match self.args.values_of("columns") {
+            _ if self.args.is_present("wide") => Column::wide(),
+            Some(names) => names.map(Column::from_name).collect::<DmgrResult<_>>()?,
+            None => Settings::get()?
+                .list_columns
+                .iter()
+                .map(|name| Column::from_name(name))
+                .collect::<DmgrResult<_>>()?,
+        };
+
+        // a process's CPU use is measured between two refreshes
+        let mut system = sysinfo::System::new();
+        if columns.contains(&Column::Cpu) {
+            thread::sleep(Duration::from_millis(constants::CPU_SAMPLE_MILLIS));
+            system.refresh_processes();
+        }
+        let system = Arc::new(system);
 
-        let header: Vec<&str> = vec!["Service", "Status", "Ports"];
-        let rows: Vec<Vec<String>> = reg.services().into_iter().map(|s| s.row()).collect();
+        let timeout = u64::from_str(self.args.value_of("timeout").unwrap())?;
+        let filter = Filter::from_args(self.args, &reg)?;
+        let services: Vec<Service> = reg
+            .loadable_services()
+            .into_iter()
+            .filter(|svc| filter.matches_config(svc))
+            .collect();
+        let snapshots =
+            status::probe_all(&services, system.clone(), Duration::from_millis(timeout));
+        let sampled_cpu = columns.contains(&Column::Cpu);
+        let mut entries: Vec<ListEntry> = services
+            .iter()
+            .zip(snapshots)
+            .map(|(svc, snapshot)| ListEntry::new(svc, snapshot, &system, sampled_cpu))
+            .filter(|entry| filter.matches_status(entry))
+            .collect();
+        sort(&mut entries, self.args.value_of("sort").unwrap());
 
-        let t = TableBuilder::new().header(header);
+        if let Some(template) = self.args.value_of("format") {
+            for entry in &entries {
+                println!("{}", render(template, entry)?);
+            }
+            return Ok(());
+        }
 
-        rows.into_iter()
-            .fold(t, |t, r| t.add_row(r))
-            .build()
-            .printstd();
+        match self.args.value_of("output").unwrap_or("table") {
+            "json" => println!("{}", serde_json::to_string_pretty(&entries)?),
+            "yaml" => print!("{}", serde_yaml::to_string(&entries)?),
+            "tsv" => print_tsv(&entries)?,
+            _ => print_table(&entries, &columns),
+        }
 
         Ok(())
     }
 }
 
-struct TableBuilder {
-    pub table: Table,
-    rows: Vec<Vec<String>>,
+// Field names of a ListEntry, in order, for TSV columns and --format
+const FIELDS: [&str; 15] = [
+    "name",
+    "status",
+    "disowned",
+    "pid",
+    "ports",
+    "open_ports",
+    "mode",
+    "repo_path",
+    "aliases",
+    "tags",
+    "hidden",
+    "uptime_secs",
+    "cpu_percent",
+    "memory_bytes",
+    "log_size_bytes",
+];
+
+// What `list` knows about a service, in every output format
+#[derive(Debug, Serialize)]
+pub struct ListEntry {
+    pub name: String,
+    pub status: State,
+    disowned: bool,
+    pid: Option<Pid>,
+    ports: Vec<u16>,
+    open_ports: Vec<u16>,
+    // how it was started, while it is running
+    mode: Option<StartMode>,
+    repo_path: PathBuf,
+    aliases: Vec<String>,
+    tags: Vec<String>,
+    hidden: bool,
+    // since the runfile was written, i.e. since dmgr started it
+    uptime_secs: Option<u64>,
+    // only sampled when the cpu column is shown
+    cpu_percent: Option<f32>,
+    memory_bytes: Option<u64>,
+    log_size_bytes: Option<u64>,
+    #[serde(skip)]
+    snapshot: Snapshot,
 }
 
-impl TableBuilder {
-    fn new() -> TableBuilder {
-        let mut table = Table::new();
-        let format = format::FormatBuilder::new()
-            .column_separator(' ')
-            .separator(
-                format::LinePosition::Title,
-                format::LineSeparator::new('-', ' ', ' ', ' '),
-            )
-            .padding(0, 1)
-            .build();
-        table.set_format(format);
+impl ListEntry {
+    pub fn new(
+        svc: &Service,
+        snapshot: Snapshot,
+        system: &sysinfo::System,
+        sampled_cpu: bool,
+    ) -> Self {
+        let mode = if snapshot.is_running() {
+            svc.runfile().ok().map(|r| r.mode)
+        } else {
+            None
+        };
+        // a container's pid may be from the runtime's VM rather than this
+        // machine, so only processes dmgr started are added up
+        let usage = snapshot
+            .pid
+            .filter(|&pid| pid > 0)
+            .filter(|_| svc.runfile().map_or(false, |r| !r.is_container))
+            .map(|pid| process::tree_usage(system, pid));
+        let uptime_secs = Some(&snapshot)
+            .filter(|s| s.is_running() && !s.disowned)
+            .and_then(|_| svc.run_file().ok())
+            .and_then(|path| fs::metadata(path).ok())
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| SystemTime::now().duration_since(t).ok())
+            .map(|d| d.as_secs());
+        let log_size_bytes = svc
+            .log_file()
+            .ok()
+            .and_then(|path| fs::metadata(path).ok())
+            .map(|m| m.len());
+
+        ListEntry {
+            name: svc.name.clone(),
+            status: snapshot.state,
+            disowned: snapshot.disowned,
+            pid: snapshot.pid,
+            ports: svc.ports.clone(),
+            open_ports: snapshot.open_ports.clone(),
+            mode,
+            repo_path: svc.repo_path.clone(),
+            aliases: svc.aliases.clone(),
+            tags: svc.tags.clone(),
+            hidden: svc.hidden,
+            uptime_secs,
+            cpu_percent: usage.filter(|_| sampled_cpu).map(|u| u.cpu_percent),
+            memory_bytes: usage.map(|u| u.memory_kb * 1024),
+            log_size_bytes,
+            snapshot,
+        }
+    }
+
+    fn fields(&self) -> DmgrResult<serde_json::Map<String, Value>> {
+        match serde_json::to_value(self)? {
+            Value::Object(fields) => Ok(fields),
+            _ => err!("could not list the fields of {:?}", &self.name),
+        }
+    }
+}
+
+// Which services `list` shows. Everything but the status is known from the
+// config, so those filters apply before any service is probed.
+struct Filter {
+    all: bool,
+    hidden_only: bool,
+    statuses: Vec<String>,
+    // members of the --group groups
+    members: Option<Vec<String>>,
+    tags: Vec<String>,
+    name: Option<Pattern>,
+}
+
+impl Filter {
+    const STATUSES: [&'static str; 5] = ["running", "waiting", "stopped", "disowned", "unknown"];
+
+    fn from_args(args: &ArgMatches, reg: &ServiceRegistry) -> DmgrResult<Self> {
+        let strings = |name| {
+            args.values_of(name)
+                .map_or(vec![], |v| v.map(String::from).collect())
+        };
+
+        let members = match args.values_of("group") {
+            Some(groups) => {
+                let mut members = vec![];
+                for group in groups {
+                    let group_members = reg
+                        .groups
+                        .get(group)
+                        .ok_or(dmgr_err!("unable to find group {:?}", group))?;
+                    members.extend(group_members.iter().cloned());
+                }
+                Some(members)
+            }
+            None => None,
+        };
+
+        let name = match args.value_of("name") {
+            Some(glob) => Some(
+                Pattern::new(glob).map_err(|e| dmgr_err!("invalid name glob {:?}: {}", glob, e))?,
+            ),
+            None => None,
+        };
+
+        Ok(Filter {
+            all: args.is_present("all"),
+            hidden_only: args.is_present("hidden"),
+            statuses: strings("status"),
+            members,
+            tags: strings("tag"),
+            name,
+        })
+    }
+
+    fn matches_config(&self, svc: &Service) -> bool {
+        let shown = if self.hidden_only {
+            svc.hidden
+        } else {
+            self.all || !svc.hidden
+        };
+
+        shown
+            && self
+                .members
+                .as_ref()
+                .map_or(true, |m| m.contains(&svc.name))
+            && (self.tags.is_empty() || svc.tags.iter().any(|t| self.tags.contains(t)))
+            && self.name.as_ref().map_or(true, |p| p.matches(&svc.name))
+    }
+
+    fn matches_status(&self, entry: &ListEntry) -> bool {
+        self.statuses.is_empty()
+            || self.statuses.iter().any(|s| match s.as_str() {
+                "disowned" => entry.disowned,
+                status => entry.status.as_str() == status,
+            })
+    }
+}
 
-        TableBuilder {
-            table,
-            rows: vec![],
+// Sorts are stable, so services stay in name order within a status
+fn sort(entries: &mut Vec<ListEntry>, key: &str) {
+    match key {
+        "status" => entries.sort_by_key(|e| e.status),
+        "uptime" => entries.sort_by(|a, b| b.uptime_secs.cmp(&a.uptime_secs)),
+        "mem" => entries.sort_by(|a, b| b.memory_bytes.cmp(&a.memory_bytes)),
+        _ => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Status,
+    Ports,
+    OpenPorts,
+    Pid,
+    Mode,
+    Uptime,
+    Cpu,
+    Mem,
+    LogSize,
+}
+
+impl Column {
+    const NAMES: [&'static str; 9] = [
+        "status",
+        "ports",
+        "open_ports",
+        "pid",
+        "mode",
+        "uptime",
+        "cpu",
+        "mem",
+        "log_size",
+    ];
+
+    fn from_name(name: &str) -> DmgrResult<Self> {
+        match name {
+            "status" => Ok(Column::Status),
+            "ports" => Ok(Column::Ports),
+            "open_ports" => Ok(Column::OpenPorts),
+            "pid" => Ok(Column::Pid),
+            "mode" => Ok(Column::Mode),
+            "uptime" => Ok(Column::Uptime),
+            "cpu" => Ok(Column::Cpu),
+            "mem" => Ok(Column::Mem),
+            "log_size" => Ok(Column::LogSize),
+            _ => err!("unknown column {:?}", name),
+        }
+    }
+
+    fn wide() -> Vec<Self> {
+        Self::NAMES
+            .iter()
+            .filter_map(|n| Self::from_name(n).ok())
+            .collect()
+    }
+
+    pub fn header(&self) -> &'static str {
+        match *self {
+            Column::Status => "Status",
+            Column::Ports => "Ports",
+            Column::OpenPorts => "Open",
+            Column::Pid => "PID",
+            Column::Mode => "Mode",
+            Column::Uptime => "Uptime",
+            Column::Cpu => "CPU%",
+            Column::Mem => "Mem",
+            Column::LogSize => "Log",
         }
     }
 
-    fn header<T>(mut self, header: Vec<T>) -> Self
-    where
-        T: AsRef<str> + std::fmt::Display,
-    {
-        self.table.set_titles(Row::from(header));
-        self
+    pub fn cell(&self, e: &ListEntry) -> String {
+        let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+        match *self {
+            Column::Status => e.snapshot.status(),
+            Column::Ports => format!("{:?}", e.ports),
+            Column::OpenPorts => format!("{:?}", e.open_ports),
+            Column::Pid => or_dash(e.pid.map(|p| p.to_string())),
+            Column::Mode => or_dash(e.mode.map(|m| m.as_str().to_string())),
+            Column::Uptime => or_dash(e.uptime_secs.map(human_duration)),
+            Column::Cpu => or_dash(e.cpu_percent.map(|c| format!("{:.1}", c))),
+            Column::Mem => or_dash(e.memory_bytes.map(human_bytes)),
+            Column::LogSize => or_dash(e.log_size_bytes.map(human_bytes)),
+        }
+    }
+}
+
+fn print_table(entries: &[ListEntry], columns: &[Column]) {
+    let mut header = vec!["Service"];
+    header.extend(columns.iter().map(|c| c.header()));
+    let t = TableBuilder::new().header(header);
+
+    entries
+        .iter()
+        .map(|e| {
+            let mut row = vec![e.name.clone()];
+            row.extend(columns.iter().map(|c| c.cell(e)));
+            row
+        })
+        .fold(t, |t, r| t.add_row(r))
+        .build()
+        .printstd();
+}
+
+// e.g. 45s, 12m, 3h20m, 2d4h
+fn human_duration(secs: u64) -> String {
+    let (d, h, m) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    match (d, h, m) {
+        (0, 0, 0) => format!("{}s", secs),
+        (0, 0, m) => format!("{}m", m),
+        (0, h, m) => format!("{}h{}m", h, m),
+        (d, h, _) => format!("{}d{}h", d, h),
+    }
+}
+
+// e.g. 512B, 1.5K, 230.0M
+fn human_bytes(bytes: u64) -> String {
+    let units = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
     }
 
-    fn add_row<T>(mut self, row: Vec<T>) -> Self
-    where
-        T: AsRef<str> + std::fmt::Display,
-    {
-        self.table.add_row(Row::from(row));
-        self
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
     }
+    format!("{:.1}{}", size, units[unit])
+}
+
+// A header line of field names, then one line per service
+fn print_tsv(entries: &[ListEntry]) -> DmgrResult {
+    println!("{}", FIELDS.join("\t"));
+
+    for entry in entries {
+        let fields = entry.fields()?;
+        let values: Vec<String> = FIELDS
+            .iter()
+            .map(|&f| fields.get(f).map(plain).unwrap_or_default())
+            .map(|v| tsv_escaped(&v))
+            .collect();
+        println!("{}", values.join("\t"));
+    }
+
+    Ok(())
+}
+
+// Backslash escapes, so a tab or newline in a command or path can't shift the
+// columns
+fn tsv_escaped(value: &str) -> String {
+    let mut out = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Fills in `{field}` placeholders; `{{` and `}}` are literal braces, and `\t`
+// and `\n` are a tab and a newline, since shells don't expand them in quotes
+fn render(template: &str, entry: &ListEntry) -> DmgrResult<String> {
+    let fields = entry.fields()?;
+    let template = template.replace("\\t", "\t").replace("\\n", "\n");
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => fail!("unclosed `{` in --format"),
+                    }
+                }
+                match fields.get(name.trim()) {
+                    Some(value) => out.push_str(&plain(value)),
+                    None => fail!(
+                        "unknown field {:?} in --format; use one of {}",
+                        name,
+                        FIELDS.join(", ")
+                    ),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    Ok(out)
+}
+
+// Lists are joined with commas and missing values are left empty
+fn plain(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref s) => s.clone(),
+        Value::Array(ref items) => items.iter().map(plain).collect::<Vec<String>>().join(","),
+        ref other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn build(self) -> Table {
-        self.table
+    #[test]
+    fn escapes_tsv_values() {
+        assert_eq!(tsv_escaped("bin/start --port 80"), "bin/start --port 80");
+        assert_eq!(tsv_escaped("a\tb\nc\rd"), "a\\tb\\nc\\rd");
+        assert_eq!(tsv_escaped("C:\\dir\\t"), "C:\\\\dir\\\\t");
     }
 }
@@ -0,0 +1,308 @@
+extern crate chrono;
+
+use self::chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDate, TimeZone};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::warn;
+
+use capture;
+use color;
+use command::DmgrResult;
+use command::{Runnable, Subcommand};
+use config::ServiceRegistry;
+use constants;
+use logfile;
+use logfile::Follower;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct LogsRunner<'a> {
+    pub args: &'a ArgMatches<'a>,
+}
+
+impl<'a> Subcommand for LogsRunner<'a> {
+    const NAME: &'static str = "logs";
+
+    fn sub_cmd() -> App<'static, 'static> {
+        SubCommand::with_name(Self::NAME)
+            .about("show the output of services or groups")
+            .arg(
+                Arg::with_name("service_or_group")
+                    .help("services or groups to show output for")
+                    .required(true)
+                    .multiple(true),
+            )
+            .arg(
+                Arg::with_name("follow")
+                    .help("keep printing output as it is written")
+                    .long("follow")
+                    .short("f"),
+            )
+            .arg(
+                Arg::with_name("lines")
+                    .help("number of lines to show per service [default: 10, or all with --since]")
+                    .long("lines")
+                    .short("n")
+                    .value_name("N"),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .help("only show lines since a time ago (e.g. 30s, 10m, 2h, 1d) or a timestamp")
+                    .long("since")
+                    .value_name("TIME"),
+            )
+            .arg(
+                Arg::with_name("stream")
+                    .help("only show lines a service wrote to this stream; needs output capture")
+                    .long("stream")
+                    .value_name("STREAM")
+                    .possible_values(&[capture::STDOUT, capture::STDERR]),
+            )
+    }
+}
+
+impl<'a> Runnable<'a> for LogsRunner<'a> {
+    fn new(args: &'a ArgMatches) -> Self {
+        LogsRunner { args }
+    }
+
+    fn run(&self) -> DmgrResult {
+        let registry = ServiceRegistry::get()?;
+
+        let names = registry.resolve_all(self.args.values_of("service_or_group").unwrap())?;
+
+        let mut sources = vec![];
+        for name in &names {
+            let svc = registry.get_service(name)?;
+            sources.push(Source {
+                path: svc.log_file()?,
+                prefix: String::new(),
+            });
+        }
+
+        // several services get a colored name prefix, like docker-compose
+        if sources.len() > 1 {
+            let width = names.iter().map(|n| n.len()).max().unwrap_or(0);
+            for (i, src) in sources.iter_mut().enumerate() {
+                let name = format!("{:width$} |", names[i], width = width);
+                src.prefix = format!("{} ", color::paint(&name, color::cycle(i)));
+            }
+        }
+
+        let since = match self.args.value_of("since") {
+            Some(s) => Some(parse_since(s)?),
+            None => None,
+        };
+        let n = match self.args.value_of("lines") {
+            Some(n) => Some(usize::from_str(n)?),
+            None if since.is_some() => None,
+            None => Some(constants::DEFAULT_LOG_LINES),
+        };
+
+        let filter = Filter {
+            since,
+            stream: self.args.value_of("stream"),
+        };
+
+        print_recent(&sources, n, &filter)?;
+
+        if self.args.is_present("follow") {
+            follow(&sources, &filter)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+struct Source {
+    path: PathBuf,
+    prefix: String,
+}
+
+struct Filter<'a> {
+    since: Option<DateTime<Local>>,
+    stream: Option<&'a str>,
+}
+
+// A line as it is shown, plus what is known about it. Captured lines carry
+// their own timestamp and stream, and JSON ones are shown in the text format.
+struct Line {
+    timestamp: Option<DateTime<Local>>,
+    stream: Option<String>,
+    text: String,
+}
+
+impl Line {
+    fn parse(line: String) -> Self {
+        match capture::parse_line(&line) {
+            Some(captured) => Line {
+                timestamp: captured.timestamp(),
+                stream: Some(captured.stream.clone()),
+                text: captured.render(),
+            },
+            None => Line {
+                timestamp: logfile::timestamp(&line),
+                stream: None,
+                text: line,
+            },
+        }
+    }
+
+    // lines without a stream, i.e. not captured, never match a stream filter
+    fn in_stream(&self, filter: &Filter) -> bool {
+        match filter.stream {
+            Some(stream) => self.stream.as_ref().map_or(false, |s| s == stream),
+            None => true,
+        }
+    }
+}
+
+struct Entry<'s> {
+    timestamp: Option<DateTime<Local>>,
+    source: &'s Source,
+    line: String,
+}
+
+// Prints the last `n` lines of each source, or all of them. Lines without a
+// timestamp of their own (e.g. stack traces) take the one from the line
+// before, so output from several sources can be interleaved in time order.
+fn print_recent(sources: &[Source], n: Option<usize>, filter: &Filter) -> DmgrResult {
+    let mut entries: Vec<Entry> = vec![];
+
+    for src in sources {
+        if !src.path.exists() {
+            warn!("no output found at {:?}", &src.path);
+            continue;
+        }
+
+        // filtering needs the whole file, not just its tail
+        let lines: Vec<Line> = match n {
+            Some(n) if filter.since.is_none() && filter.stream.is_none() => {
+                logfile::tail(&src.path, n)?
+            }
+            _ => logfile::lines(&src.path)?,
+        }
+        .into_iter()
+        .map(Line::parse)
+        .collect();
+
+        // e.g. the output of a process started without capture
+        let since = match filter.since {
+            Some(_) if lines.iter().all(|l| l.timestamp.is_none()) => {
+                warn!(
+                    "{:?} has no timestamps to compare with --since, showing all of it",
+                    &src.path
+                );
+                None
+            }
+            since => since,
+        };
+
+        let mut last_timestamp = None;
+        let mut src_entries: Vec<Entry> = vec![];
+        for line in lines {
+            if line.timestamp.is_some() {
+                last_timestamp = line.timestamp;
+            }
+
+            if let Some(since) = since {
+                if last_timestamp.map_or(true, |ts| ts < since) {
+                    continue;
+                }
+            }
+
+            if !line.in_stream(filter) {
+                continue;
+            }
+
+            src_entries.push(Entry {
+                timestamp: last_timestamp,
+                source: src,
+                line: line.text,
+            });
+        }
+
+        let skip = n.map_or(0, |n| src_entries.len().saturating_sub(n));
+        entries.extend(src_entries.into_iter().skip(skip));
+    }
+
+    if sources.len() > 1 {
+        entries.sort_by_key(|e| e.timestamp);
+    }
+
+    for e in entries {
+        println!("{}{}", e.source.prefix, e.line);
+    }
+
+    Ok(())
+}
+
+fn follow(sources: &[Source], filter: &Filter) -> DmgrResult {
+    let mut followers: Vec<(&Source, Follower)> = sources
+        .iter()
+        .map(|src| (src, Follower::new(src.path.clone())))
+        .collect();
+
+    loop {
+        for &mut (src, ref mut follower) in followers.iter_mut() {
+            for line in follower.poll()?.into_iter().map(Line::parse) {
+                if line.in_stream(filter) {
+                    println!("{}{}", src.prefix, line.text);
+                }
+            }
+        }
+
+        io::stdout().flush()?;
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+// Accepts a relative time like "10m" or an absolute one like "2019-03-01",
+// "2019-03-01 12:00:00" or RFC 3339
+fn parse_since(s: &str) -> DmgrResult<DateTime<Local>> {
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        fail!(
+            "{:?} needs a unit, e.g. {}s, {}m, {}h or {}d",
+            s,
+            s,
+            s,
+            s,
+            s
+        )
+    }
+
+    if let Some(unit) = s.chars().last() {
+        let amount = &s[..s.len() - unit.len_utf8()];
+        if let Ok(n) = u64::from_str(amount) {
+            let unit_secs = match unit {
+                's' => 1,
+                'm' => 60,
+                'h' => 60 * 60,
+                'd' => 24 * 60 * 60,
+                _ => fail!("unknown time unit {:?} in {:?}; use s, m, h or d", unit, s),
+            };
+            return n
+                .checked_mul(unit_secs)
+                .and_then(|secs| ChronoDuration::from_std(Duration::from_secs(secs)).ok())
+                .and_then(|ago| Local::now().checked_sub_signed(ago))
+                .ok_or(dmgr_err!("{:?} is too long ago", s));
+        }
+    }
+
+    if let Some(ts) = logfile::timestamp(s) {
+        return Ok(ts);
+    }
+
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|dt| Local.from_local_datetime(&dt).single())
+        .ok_or(dmgr_err!(
+            "could not parse {:?} as a time; use e.g. 10m or \"2019-03-01 12:00:00\"",
+            s
+        ))
+}
@@ -1,12 +1,17 @@
 extern crate backtrace;
 extern crate serde;
 extern crate serde_json;
+extern crate serde_yaml;
 extern crate toml;
 
 use self::backtrace::Backtrace;
 use self::serde_json::Error as JsonErr;
+use self::serde_yaml::Error as YamlErr;
 use self::toml::de;
 use clap::{App, ArgMatches};
+use log::{error, info};
+
+use table::TableBuilder;
 
 use std::ffi::OsString;
 use std::fmt;
@@ -14,10 +19,17 @@ use std::io;
 use std::num::ParseIntError;
 use std::path::StripPrefixError;
 
+pub mod config;
+pub mod group;
+pub mod health;
 pub mod list;
+pub mod logs;
 pub mod register;
+pub mod restart;
 pub mod start;
 pub mod stop;
+pub mod supervise;
+pub mod top;
 
 pub trait Runnable<'a> {
     fn new(args: &'a ArgMatches<'a>) -> Self;
@@ -32,10 +44,45 @@ pub trait Subcommand {
 
 pub type DmgrResult<T = ()> = Result<T, DmgrErr>;
 
+// Describes what happened to a single service, e.g. "started" or "not running"
+pub type Outcome = DmgrResult<&'static str>;
+
+// Logs the outcome of an action for each service and prints a summary table,
+// failing if any of them failed
+pub fn report(action: &str, results: Vec<(String, Outcome)>) -> DmgrResult {
+    let mut failed = vec![];
+    let mut t = TableBuilder::new().header(vec!["Service", "Result"]);
+
+    for (name, result) in results {
+        let outcome = match result {
+            Ok(outcome) => {
+                info!("{} {:?}: {}", action, name, outcome);
+                outcome.to_string()
+            }
+            Err(e) => {
+                error!("{} {:?}: {}", action, name, e);
+                failed.push(name.clone());
+                format!("failed: {}", e)
+            }
+        };
+        t = t.add_row(vec![name, outcome]);
+    }
+
+    t.build().printstd();
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        fail!("failed to {} {:?}", action, failed)
+    }
+}
+
 #[derive(Debug)]
 pub struct DmgrErr {
     msg: String,
     pub stacktrace: Backtrace,
+    // already reported to the user, so dmgr only has to exit with 1
+    pub silent: bool,
 }
 
 impl DmgrErr {
@@ -44,6 +91,16 @@ impl DmgrErr {
         DmgrErr {
             msg: String::from(content),
             stacktrace: bt,
+            silent: false,
+        }
+    }
+
+    // Fails a command whose output already says what went wrong, e.g. JSON
+    // that has to be all there is on stdout
+    pub fn silent(content: &str) -> Self {
+        DmgrErr {
+            silent: true,
+            ..DmgrErr::new(content)
         }
     }
 }
@@ -78,6 +135,12 @@ impl From<JsonErr> for DmgrErr {
     }
 }
 
+impl From<YamlErr> for DmgrErr {
+    fn from(err: YamlErr) -> Self {
+        DmgrErr::new(&err.to_string())
+    }
+}
+
 impl From<StripPrefixError> for DmgrErr {
     fn from(err: StripPrefixError) -> Self {
         DmgrErr::new(&err.to_string())
@@ -8,12 +8,11 @@ use log::{info, warn};
 
 use command::DmgrResult;
 use command::{Runnable, Subcommand};
+use config;
 use config::ServiceRegistry;
 use constants;
 use service::Service;
 use std::ffi::OsStr;
-use std::fs;
-use std::fs::DirEntry;
 
 #[derive(Debug)]
 pub struct RegisterRunner<'a> {
@@ -88,14 +87,15 @@ fn register_single<'a>(args: &'a ArgMatches) -> DmgrResult {
 
 fn unregister<'a>(args: &'a ArgMatches) -> DmgrResult {
     let svc = args.value_of("service").unwrap();
-    let mut registry = ServiceRegistry::get()?;
-
-    match registry.content.remove(svc) {
-        Some(_) => info!("successfully removed {:?} from service registry", svc),
-        None => warn!("no entry {:?} found in {:?}", svc, registry.path),
-    };
+    let registry = ServiceRegistry::get()?;
+    let registered = registry.content.contains_key(svc);
+    let registry = registry.remove_svc(svc).save()?;
 
-    registry.save()?;
+    if registered {
+        info!("successfully removed {:?} from service registry", svc);
+    } else {
+        warn!("no entry {:?} found in {:?}", svc, registry.path);
+    }
 
     Ok(())
 }
@@ -105,10 +105,10 @@ fn register_all() -> DmgrResult {
     ensure_dir_exists(&cfg_dir)?;
     info!("registering all services in {:?}...", cfg_dir);
 
-    let json_files = find_all_json_files(cfg_dir)?;
-    let svc_names: Vec<&OsStr> = json_files.iter().map(path_to_svc_name).collect();
+    let cfg_files = config::find_all_service_configs(&cfg_dir)?;
+    let svc_names: Vec<&OsStr> = cfg_files.iter().map(path_to_svc_name).collect();
 
-    let registry = json_files
+    let registry = cfg_files
         .iter()
         .fold(ServiceRegistry::get(), |r, cfg| r?.add_cfg(cfg));
 
@@ -128,8 +128,8 @@ fn register_default<'a>(_args: &'a ArgMatches) -> DmgrResult {
     ensure_dir_exists(&cfg_dir)?;
     info!("registering default services in {:?}...", cfg_dir);
 
-    let json_files = find_all_json_files(cfg_dir)?;
-    let services: Vec<Service> = json_files
+    let cfg_files = config::find_all_service_configs(&cfg_dir)?;
+    let services: Vec<Service> = cfg_files
         .iter()
         .map(Service::from_path)
         .filter_map(Result::ok)
@@ -148,29 +148,13 @@ fn register_default<'a>(_args: &'a ArgMatches) -> DmgrResult {
 }
 
 fn find_svc_config(svc: &str, cwd: io::Result<PathBuf>) -> DmgrResult<PathBuf> {
-    let config_file = cwd?
-        .join(constants::SERVICE_CONFIG_DIR)
-        .join(svc)
-        .with_extension("json");
+    let cfg_dir = cwd?.join(constants::SERVICE_CONFIG_DIR);
 
-    config_file
+    config::find_service_config(&cfg_dir, svc)?
         .canonicalize()
         .map_err(|_| dmgr_err!("unable to find a config file for service '{}'", svc))
 }
 
-fn find_all_json_files(dir: PathBuf) -> DmgrResult<Vec<PathBuf>> {
-    let json_files: Vec<PathBuf> = fs::read_dir(dir)?
-        .into_iter()
-        .filter_map(Result::ok)
-        .map(|dir_entry: DirEntry| dir_entry.path())
-        .filter(|p| p.extension().unwrap_or(OsStr::new("not json")) == "json")
-        .map(|p| p.canonicalize())
-        .filter_map(Result::ok)
-        .collect();
-
-    Ok(json_files)
-}
-
 fn ensure_dir_exists(rel_path: &PathBuf) -> DmgrResult {
     let cwd = env::current_dir()?;
     let abs_path = cwd.join(rel_path);
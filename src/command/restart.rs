@@ -0,0 +1,115 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::info;
+
+use command;
+use command::start;
+use command::start::StartOptions;
+use command::start::WaitOptions;
+use command::stop;
+use command::DmgrResult;
+use command::Outcome;
+use command::{Runnable, Subcommand};
+use config::ServiceRegistry;
+use deps;
+use service::Service;
+
+#[derive(Debug)]
+pub struct RestartRunner<'a> {
+    pub args: &'a ArgMatches<'a>,
+}
+
+impl<'a> Subcommand for RestartRunner<'a> {
+    const NAME: &'static str = "restart";
+
+    fn sub_cmd() -> App<'static, 'static> {
+        SubCommand::with_name(Self::NAME)
+            .about("restart a service or group the way it was last started")
+            .arg(
+                Arg::with_name("service_or_group")
+                    .help("service or group to restart")
+                    .required_unless("all")
+                    .conflicts_with("all"),
+            )
+            .arg(
+                Arg::with_name("all")
+                    .help("restart all registered services")
+                    .long("all")
+                    .short("a")
+                    .conflicts_with("service_or_group"),
+            )
+            .args(&start::wait_args())
+    }
+}
+
+impl<'a> Runnable<'a> for RestartRunner<'a> {
+    fn new(args: &'a ArgMatches) -> Self {
+        RestartRunner { args }
+    }
+
+    fn run(&self) -> DmgrResult {
+        let registry = ServiceRegistry::get()?;
+        let (names, broken) = match self.args.value_of("service_or_group") {
+            Some(target) => (registry.resolve(target)?, vec![]),
+            None => {
+                let (services, broken) = registry.services();
+                (services.into_iter().map(|s| s.name).collect(), broken)
+            }
+        };
+
+        let readiness = WaitOptions::from_args(self.args)?;
+        let no_wait = self.args.is_present("no_wait");
+
+        // runfiles are removed on stop, so read the start modes up front
+        let plans: Vec<(Service, StartOptions)> = deps::order_among(&registry, &names)
+            .into_iter()
+            .map(|svc| {
+                let opts = StartOptions::from_runfile(&svc, readiness, no_wait);
+                (svc, opts)
+            })
+            .collect();
+
+        if plans.len() == 1 && broken.is_empty() {
+            let (ref svc, ref opts) = plans[0];
+            return restart(svc, opts);
+        }
+
+        let mut results = restart_in_order(plans);
+        results.extend(broken.into_iter().map(|(name, e)| (name, Err(e))));
+        command::report("restart", results)
+    }
+}
+
+pub fn restart(svc: &Service, opts: &StartOptions) -> DmgrResult {
+    info!("restarting {:?} in {:?} mode...", &svc.name, opts.mode);
+
+    if svc.is_running() {
+        stop::stop_service(svc)?;
+    }
+    start::start_service(svc, opts)
+}
+
+// Stops everything in reverse dependency order before starting anything again
+fn restart_in_order(plans: Vec<(Service, StartOptions)>) -> Vec<(String, Outcome)> {
+    let mut stop_failures: Vec<(String, Outcome)> = plans
+        .iter()
+        .rev()
+        .filter(|&&(ref svc, _)| svc.is_running())
+        .filter_map(|&(ref svc, _)| match stop::stop_service(svc) {
+            Ok(()) => None,
+            Err(e) => Some((svc.name.clone(), Err(e))),
+        })
+        .collect();
+
+    let mut results: Vec<(String, Outcome)> = plans
+        .iter()
+        .filter(|&&(ref svc, _)| !stop_failures.iter().any(|f| f.0 == svc.name))
+        .map(|&(ref svc, ref opts)| {
+            info!("starting {:?} in {:?} mode...", &svc.name, opts.mode);
+            let result = start::start_service(svc, opts).map(|_| "restarted");
+            (svc.name.clone(), result)
+        })
+        .collect();
+
+    results.append(&mut stop_failures);
+    results
+}
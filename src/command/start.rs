@@ -1,19 +1,42 @@
 extern crate shlex;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use log::info;
+use log::{info, warn};
 
+use command;
+use command::stop;
+use command::supervise::SuperviseRunner;
 use command::DmgrResult;
+use command::Outcome;
 use command::{Runnable, Subcommand};
+use config::CaptureMode;
 use config::Pid;
 use config::Runfile;
 use config::ServiceRegistry;
+use config::StartMode;
+use constants;
+use container;
+use deps;
+use process;
+use rotation::LogRotation;
 use service::Service;
+use settings::Settings;
+use std::env;
+use std::fs;
 use std::fs::create_dir_all;
 use std::fs::File;
 use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
 use std::path::PathBuf;
+use std::process::Child;
 use std::process::Command;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use watcher::Watcher;
 
 #[derive(Debug)]
 pub struct StartRunner<'a> {
@@ -53,6 +76,14 @@ impl<'a> Subcommand for StartRunner<'a> {
                     .short("d")
                     .conflicts_with("container"),
             )
+            .arg(
+                Arg::with_name("watch")
+                    .help("restart the service when files in its repo change")
+                    .long("watch")
+                    .short("w")
+                    .requires("dev_mode")
+                    .conflicts_with("attach"),
+            )
             .arg(
                 Arg::with_name("update_image")
                     .help("pull updated image for container")
@@ -60,93 +91,597 @@ impl<'a> Subcommand for StartRunner<'a> {
                     .short("u")
                     .requires("container"),
             )
+            .arg(
+                Arg::with_name("capture")
+                    .help("how to capture output: as is, or with each line timestamped and tagged out/err as text or JSON")
+                    .long("capture")
+                    .value_name("MODE")
+                    .possible_values(&CaptureMode::names())
+                    .conflicts_with("container"),
+            )
             .arg(
                 Arg::with_name("attach")
                     .help("attach your console to command")
                     .long("attach")
                     .short("t")
-                    .conflicts_with("all"),
+                    .conflicts_with("all")
+                    .conflicts_with("no_wait"),
             )
+            .args(&wait_args())
     }
 }
 
+// Readiness args shared by every subcommand that starts services
+pub fn wait_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("no_wait")
+            .help("return once the process is spawned, without waiting for it to be ready")
+            .long("no_wait")
+            .short("n"),
+        Arg::with_name("timeout")
+            .help(
+                "seconds to wait for the service to become ready [default: start_timeout setting]",
+            )
+            .long("timeout")
+            .value_name("SECONDS"),
+        Arg::with_name("interval")
+            .help("milliseconds between readiness checks")
+            .long("interval")
+            .value_name("MILLIS")
+            .default_value(constants::DEFAULT_POLL_INTERVAL_MILLIS),
+    ]
+}
+
 impl<'a> Runnable<'a> for StartRunner<'a> {
     fn new(args: &'a ArgMatches) -> Self {
         StartRunner { args }
     }
 
     fn run(&self) -> DmgrResult {
-        println!("Matches = {:#?}", self.args);
-
         match self.args {
-            //            a if a.is_present("all") => start_all(a),
-            //            c if c.is_present("container") => start_container(c),
-            //            d if d.is_present("dev_mode") => start_dev_mode(d),
+            a if a.is_present("all") => start_all(a),
             default => start(default),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct WaitOptions {
+    pub timeout: Duration,
+    pub interval: Duration,
+}
+
+impl WaitOptions {
+    pub fn from_args(args: &ArgMatches) -> DmgrResult<Self> {
+        let timeout = match args.value_of("timeout") {
+            Some(secs) => Duration::from_secs(u64::from_str(secs)?),
+            None => Settings::get()?.start_timeout,
+        };
+        let interval = u64::from_str(args.value_of("interval").unwrap())?;
+
+        Ok(WaitOptions {
+            timeout,
+            interval: Duration::from_millis(interval),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StartOptions {
+    pub mode: StartMode,
+    pub attached: bool,
+    pub watch: bool,
+    pub update_image: bool,
+    pub no_wait: bool,
+    pub readiness: WaitOptions,
+    // exact command to run instead of the one in the service config
+    pub command: Option<String>,
+    // overrides the service's log_capture
+    pub capture: Option<CaptureMode>,
+}
+
+impl StartOptions {
+    fn from_args(args: &ArgMatches) -> DmgrResult<Self> {
+        let mode = if args.is_present("container") {
+            StartMode::Container
+        } else if args.is_present("dev_mode") {
+            StartMode::DevMode
+        } else {
+            StartMode::Process
+        };
+
+        Ok(StartOptions {
+            mode,
+            attached: args.is_present("attach"),
+            watch: args.is_present("watch"),
+            update_image: args.is_present("update_image"),
+            no_wait: args.is_present("no_wait"),
+            readiness: WaitOptions::from_args(args)?,
+            command: None,
+            capture: match args.value_of("capture") {
+                Some(c) => Some(CaptureMode::from_str(c)?),
+                None => None,
+            },
+        })
+    }
+
+    // Repeats however the service was last started, according to its
+    // runfile, falling back to a plain process start
+    pub fn from_runfile(svc: &Service, readiness: WaitOptions, no_wait: bool) -> Self {
+        let (mode, command, capture) = match svc.runfile() {
+            Ok(ref r) if r.is_container => (StartMode::Container, r.command.clone(), None),
+            Ok(r) => (r.mode, r.command, Some(r.capture)),
+            Err(_) => (StartMode::Process, None, None),
+        };
+
+        StartOptions {
+            mode,
+            attached: false,
+            watch: false,
+            update_image: false,
+            no_wait,
+            readiness,
+            command,
+            capture,
+        }
+    }
+
+    pub fn wait(&self) -> Option<WaitOptions> {
+        if self.no_wait {
+            None
+        } else {
+            Some(self.readiness)
+        }
+    }
+
+    // Dependencies always start as plain processes and are always waited on,
+    // whatever was asked of the services that need them
+    fn for_dependency(&self) -> Self {
+        StartOptions {
+            mode: StartMode::Process,
+            attached: false,
+            watch: false,
+            update_image: false,
+            no_wait: false,
+            readiness: self.readiness,
+            command: None,
+            capture: None,
+        }
+    }
+
+    fn capture_for(&self, svc: &Service) -> CaptureMode {
+        self.capture.unwrap_or(svc.log_capture)
+    }
+}
+
 fn start<'a>(args: &'a ArgMatches) -> DmgrResult {
-    let svc_name = args.value_of("service_or_group").unwrap();
-    let attached = args.is_present("attach");
-    println!("attached = {:?}", attached);
-    let svc = ServiceRegistry::get()?.get_service(svc_name)?;
+    let target = args.value_of("service_or_group").unwrap();
+    let opts = StartOptions::from_args(args)?;
+    let registry = ServiceRegistry::get()?;
+    let names = registry.resolve(target)?;
+    let order = deps::start_order(&registry, &names)?;
+
+    if names.len() > 1 && (opts.attached || opts.watch) {
+        fail!("cannot attach to or watch group {:?}", target)
+    }
+
+    if order.len() == 1 {
+        let svc = &order[0];
+        if svc.is_running() {
+            info!("{:?} is already running", &svc.name);
+            return Ok(());
+        }
+        return start_service(svc, &opts);
+    }
+
+    info!(
+        "starting {:?} in order: {:?}",
+        target,
+        order.iter().map(|s| &s.name).collect::<Vec<&String>>()
+    );
+    command::report("start", start_in_order(order, &names, &opts))
+}
+
+fn start_all<'a>(args: &'a ArgMatches) -> DmgrResult {
+    let opts = StartOptions::from_args(args)?;
+    let registry = ServiceRegistry::get()?;
+    let (services, broken) = registry.services();
+    let names: Vec<String> = services.iter().map(|s| s.name.clone()).collect();
+
+    let order = deps::start_order(&registry, &names).unwrap_or_else(|e| {
+        warn!("{}; starting services in registry order", e);
+        services
+    });
+
+    info!(
+        "starting all services in order: {:?}",
+        order.iter().map(|s| &s.name).collect::<Vec<&String>>()
+    );
+    let mut results = start_in_order(order, &names, &opts);
+    results.extend(broken.into_iter().map(|(name, e)| (name, Err(e))));
+    command::report("start", results)
+}
+
+// Starts services in dependency order, skipping any that are already running.
+// Dependencies are waited on either way, as one started moments ago, e.g. by
+// another `dmgr start`, may still be coming up. A service whose dependency
+// failed is not started, but independent services still are.
+fn start_in_order(
+    order: Vec<Service>,
+    requested: &[String],
+    opts: &StartOptions,
+) -> Vec<(String, Outcome)> {
+    let needed: Vec<String> = order.iter().flat_map(|s| s.depends_on.clone()).collect();
+    let mut failed: Vec<String> = vec![];
+    let mut results = vec![];
+
+    for svc in order {
+        let result = match svc.depends_on.iter().find(|d| failed.contains(d)) {
+            Some(dep) => err!("dependency {:?} failed to start", dep),
+            None if svc.is_running() && needed.contains(&svc.name) => {
+                wait_for_service(&svc, None, opts.readiness).map(|_| "already running")
+            }
+            None if svc.is_running() => Ok("already running"),
+            None if requested.contains(&svc.name) => start_service(&svc, opts).map(|_| "started"),
+            None => start_service(&svc, &opts.for_dependency()).map(|_| "started"),
+        };
+
+        if result.is_err() {
+            failed.push(svc.name.clone());
+        }
+        results.push((svc.name.clone(), result));
+    }
 
-    // TODO: handle if arg is group
-    start_as_process(svc, attached)
+    results
 }
 
-fn start_as_process(svc: Service, attached: bool) -> DmgrResult {
+pub fn start_service(svc: &Service, opts: &StartOptions) -> DmgrResult {
+    match opts.mode {
+        StartMode::Container => start_container(svc, opts),
+        StartMode::DevMode => start_dev_mode(svc, opts),
+        StartMode::Process => start_as_process(svc, opts),
+    }
+}
+
+fn start_as_process(svc: &Service, opts: &StartOptions) -> DmgrResult {
     info!("starting {:?} as process...", &svc.name);
 
-    let cmd = cmd_for(&svc.start_process)?;
-    if attached {
+    let script = script_for(&svc.start_process, opts, "start_process")?;
+    launch(svc, script, StartMode::Process, opts)
+}
+
+fn start_dev_mode(svc: &Service, opts: &StartOptions) -> DmgrResult {
+    info!("starting {:?} in dev mode...", &svc.name);
+
+    let script = script_for(&svc.start_dev_mode, opts, "start_dev_mode")?;
+    if opts.watch {
+        watch_and_restart(svc, &script, opts.capture_for(svc), opts.wait())
+    } else {
+        launch(svc, script, StartMode::DevMode, opts)
+    }
+}
+
+fn start_container(svc: &Service, opts: &StartOptions) -> DmgrResult {
+    let runtime = container::runtime();
+    info!(
+        "starting {:?} as container with {}...",
+        &svc.name,
+        runtime.name()
+    );
+
+    if opts.update_image {
+        let image = svc.image();
+        info!("pulling {}...", &image);
+        runtime.pull(&image)?;
+    }
+
+    let script = opts.command.clone().or(svc.start_container.clone());
+
+    if opts.attached {
+        let cmd = match script {
+            Some(ref s) => cmd_for(s)?,
+            None => runtime.command(&container::run_args(svc, false)),
+        };
+        return start_attached(cmd);
+    }
+
+    let (id, command) = match script {
+        Some(s) => (run_container_script(svc, &s)?, s),
+        None => {
+            let args = container::run_args(svc, true);
+            let command = quoted(runtime.program().to_string_lossy().as_ref(), &args);
+            (runtime.run(&args)?, command)
+        }
+    };
+    info!("started container {}", &id);
+
+    let pid = match runtime.pid(&id) {
+        Ok(pid) => Some(pid),
+        Err(e) => {
+            warn!("could not find the pid of container {}: {}", &id, e);
+            None
+        }
+    };
+    let runfile = Runfile {
+        pid,
+        started: None,
+        is_container: true,
+        mode: StartMode::Container,
+        command: Some(command),
+        capture: CaptureMode::Raw,
+        log_offset: log_size(svc)?,
+        container_id: Some(id),
+        container_runtime: runtime.program().to_str().map(String::from),
+    };
+    svc.update_runfile(runfile)?;
+
+    match opts.wait() {
+        Some(wait) => wait_for_service(svc, None, wait),
+        None => Ok(()),
+    }
+}
+
+// start_container scripts are expected to print the container id as the
+// last line of their output, as `docker run -d` does
+fn run_container_script(svc: &Service, script: &str) -> DmgrResult<container::ContainerId> {
+    rotate_log(svc)?;
+    let output = cmd_for(script)?
+        .current_dir(&svc.repo_path)
+        .stderr(out_file(svc)?)
+        .output()?;
+
+    if !output.status.success() {
+        fail!(
+            "start_container for {:?} failed with {}; see {:?}",
+            &svc.name,
+            output.status,
+            svc.log_file()?
+        )
+    }
+
+    container::last_line(&String::from_utf8_lossy(&output.stdout)).ok_or(dmgr_err!(
+        "start_container for {:?} did not print a container id",
+        &svc.name
+    ))
+}
+
+fn launch(svc: &Service, script: String, mode: StartMode, opts: &StartOptions) -> DmgrResult {
+    if opts.attached {
+        let mut cmd = cmd_for(&script)?;
+        cmd.current_dir(&svc.repo_path);
         start_attached(cmd)
     } else {
-        spawn(svc, cmd)
+        let mut child = spawn(svc, script, mode, opts.capture_for(svc), true)?;
+        match opts.wait() {
+            Some(opts) => wait_for_service(svc, Some(&mut child), opts),
+            None => Ok(()),
+        }
+    }
+}
+
+// Runs the dev mode command in the foreground, restarting it whenever a
+// watched file in the repo changes. Only returns on error.
+fn watch_and_restart(
+    svc: &Service,
+    script: &str,
+    capture: CaptureMode,
+    wait: Option<WaitOptions>,
+) -> DmgrResult {
+    let mut watcher = Watcher::new(&svc.repo_path, &svc.watch)?;
+    info!("watching {:?} for changes...", &svc.repo_path);
+
+    loop {
+        // stays in dmgr's process group, so Ctrl-C stops the service too
+        let mut child = spawn(svc, script.to_string(), StartMode::DevMode, capture, false)?;
+
+        if let Some(opts) = wait {
+            if let Err(e) = wait_for_service(svc, Some(&mut child), opts) {
+                warn!("{}", e);
+            }
+        }
+
+        let changed = wait_for_changes(svc, &mut watcher, &mut child)?;
+        info!(
+            "{} file(s) changed (first: {:?}), restarting {:?}...",
+            changed.len(),
+            &changed[0],
+            &svc.name
+        );
+
+        stop::stop_process(svc, child.id() as Pid)?;
+        child.wait()?;
+    }
+}
+
+fn wait_for_changes(
+    svc: &Service,
+    watcher: &mut Watcher,
+    child: &mut Child,
+) -> DmgrResult<Vec<PathBuf>> {
+    let mut exited = false;
+
+    loop {
+        thread::sleep(watcher.interval());
+        let changed = watcher.changes()?;
+        if !changed.is_empty() {
+            return debounce(watcher, changed);
+        }
+
+        if !exited {
+            if let Some(status) = child.try_wait()? {
+                exited = true;
+                warn!(
+                    "{:?} exited with {}; restarting on next change",
+                    &svc.name, status
+                );
+            }
+        }
     }
 }
 
-fn cmd_for(cmd: &Option<String>) -> DmgrResult<Command> {
-    let start = cmd
+// Keeps collecting changes until the repo has been quiet for one interval, so
+// a burst of writes (e.g. a branch checkout) triggers a single restart.
+fn debounce(watcher: &mut Watcher, mut changed: Vec<PathBuf>) -> DmgrResult<Vec<PathBuf>> {
+    loop {
+        thread::sleep(watcher.interval());
+        let more = watcher.changes()?;
+        if more.is_empty() {
+            return Ok(changed);
+        }
+        changed.extend(more);
+    }
+}
+
+fn script_for(cmd: &Option<String>, opts: &StartOptions, kind: &str) -> DmgrResult<String> {
+    opts.command
         .clone()
-        .ok_or(dmgr_err!("no start_process script found"))?;
-    let args =
-        shlex::split(start.as_str()).ok_or(dmgr_err!("problem parsing command: {:?}", start))?;
+        .or(cmd.clone())
+        .ok_or(dmgr_err!("no {} script found", kind))
+}
+
+pub fn cmd_for(script: &str) -> DmgrResult<Command> {
+    let args = shlex::split(script).ok_or(dmgr_err!("problem parsing command: {:?}", script))?;
+    if args.is_empty() {
+        fail!("empty command")
+    }
 
     let mut cmd = Command::new(&args[0]);
     cmd.args(args[1..].iter());
     Ok(cmd)
 }
 
+fn quoted(program: &str, args: &[String]) -> String {
+    let mut parts = vec![shlex::quote(program).to_string()];
+    parts.extend(args.iter().map(|a| shlex::quote(a).to_string()));
+    parts.join(" ")
+}
+
 fn start_attached(mut cmd: Command) -> DmgrResult {
     let status = cmd.status()?;
     info!("exited with status {:?}", status.code().unwrap());
     Ok(())
 }
 
-fn spawn(svc: Service, mut cmd: Command) -> DmgrResult {
-    let child = cmd
-        .stderr(out_file(&svc)?)
-        .stdout(out_file(&svc)?)
-        .spawn()?;
+// Detached services run in their own session so that stopping them can signal
+// everything they spawn, not just the pid recorded in the runfile. Scripts run
+// in the service's repo, so the command recorded for restarts means the same
+// thing wherever dmgr is run from.
+fn spawn(
+    svc: &Service,
+    script: String,
+    mode: StartMode,
+    capture: CaptureMode,
+    detached: bool,
+) -> DmgrResult<Child> {
+    let mut cmd = match capture {
+        CaptureMode::Raw => cmd_for(&script)?,
+        _ => supervised(&script, &svc.log_file()?, capture)?,
+    };
+    cmd.current_dir(&svc.repo_path);
+    if detached {
+        process::detach(&mut cmd);
+    } else {
+        cmd.stdin(Stdio::null());
+    }
+
+    rotate_log(svc)?;
+    let log_offset = log_size(svc)?;
+    let child = cmd.stderr(out_file(svc)?).stdout(out_file(svc)?).spawn()?;
 
     let runfile = Runfile {
-        pid: child.id() as Pid,
+        pid: Some(child.id() as Pid),
+        started: process::start_time(child.id() as Pid),
         is_container: false,
+        mode,
+        command: Some(script),
+        capture,
+        log_offset,
+        container_id: None,
+        container_runtime: None,
     };
-    println!("da file = {:?}", runfile);
     svc.update_runfile(runfile)?;
 
-    //    wait_for_service(svc);
+    Ok(child)
+}
+
+// Rotates the log before a new instance of the service writes to it, but
+// never while one is running, as that would carry on writing to the rotated
+// file
+fn rotate_log(svc: &Service) -> DmgrResult {
+    if svc.is_running() {
+        warn!("{:?} is running, not rotating its log", &svc.name);
+        return Ok(());
+    }
 
+    LogRotation::resolve(&svc.log_rotation)?.rotate_if_needed(&svc.log_file()?)?;
     Ok(())
 }
 
-//fn wait_for_service(svc: Service) -> DmgrResult {
-//    Ok(())
-//}
+// Runs the script under `dmgr supervise`, which writes the tagged output to
+// the log itself. Its pid is the one recorded in the runfile, and as the first
+// process in the session it leads the group the script runs in.
+fn supervised(script: &str, log: &PathBuf, capture: CaptureMode) -> DmgrResult<Command> {
+    let mut cmd = Command::new(env::current_exe()?);
+    cmd.args(Settings::get()?.args())
+        .arg(SuperviseRunner::NAME)
+        .arg("--capture")
+        .arg(capture.as_str())
+        .arg("--log")
+        .arg(log)
+        .arg("--")
+        .arg(script);
+    Ok(cmd)
+}
+
+// Polls until the service reports ready. A child that exits with a failure
+// status is reported immediately; one that exits cleanly may have daemonized,
+// so polling continues until the timeout.
+fn wait_for_service(svc: &Service, mut child: Option<&mut Child>, opts: WaitOptions) -> DmgrResult {
+    let started = Instant::now();
+    print!("waiting for {:?} to become ready ", &svc.name);
+    io::stdout().flush()?;
+
+    loop {
+        if svc.is_ready() {
+            println!(" ready");
+            info!(
+                "{:?} is ready after {}ms",
+                &svc.name,
+                started.elapsed().as_millis()
+            );
+            return Ok(());
+        }
+
+        if let Some(status) = match child {
+            Some(ref mut c) => c.try_wait()?,
+            None => None,
+        } {
+            if !status.success() {
+                println!(" failed");
+                fail!(
+                    "{:?} exited with {} before becoming ready; see {:?}",
+                    &svc.name,
+                    status,
+                    svc.log_file()?
+                )
+            }
+        }
+
+        if started.elapsed() >= opts.timeout {
+            println!(" timed out");
+            fail!(
+                "{:?} did not become ready within {}s; see {:?}",
+                &svc.name,
+                opts.timeout.as_secs(),
+                svc.log_file()?
+            )
+        }
+
+        print!(".");
+        io::stdout().flush()?;
+        thread::sleep(opts.interval);
+    }
+}
 
 fn out_file(svc: &Service) -> DmgrResult<File> {
     let log_path = svc.log_file()?;
@@ -162,6 +697,10 @@ fn out_file(svc: &Service) -> DmgrResult<File> {
     Ok(file)
 }
 
+fn log_size(svc: &Service) -> DmgrResult<u64> {
+    Ok(fs::metadata(svc.log_file()?).map(|m| m.len()).unwrap_or(0))
+}
+
 fn ensure_parent_dir_exists(filepath: &PathBuf) -> DmgrResult {
     let parent_dir = filepath.parent().ok_or(dmgr_err!(
         "could not determine parent dir of filepath {:?}",
@@ -170,19 +709,3 @@ fn ensure_parent_dir_exists(filepath: &PathBuf) -> DmgrResult {
     create_dir_all(parent_dir)?;
     Ok(())
 }
-
-//fn start_all<'a>(args: &'a ArgMatches) -> DmgrResult {
-//    Ok(())
-//}
-//
-//fn start_container<'a>(args: &'a ArgMatches) -> DmgrResult {
-//    Ok(())
-//}
-//
-//fn start_dev_mode<'a>(args: &'a ArgMatches) -> DmgrResult {
-//    Ok(())
-//}
-//
-//fn start_group(name: &str) -> DmgrResult {
-//    Ok(())
-//}
@@ -2,13 +2,21 @@ extern crate shlex;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
 use log::{info, warn};
-use sysinfo::ProcessExt;
-use sysinfo::Signal;
-use sysinfo::SystemExt;
 
+use command;
 use command::DmgrResult;
+use command::Outcome;
 use command::{Runnable, Subcommand};
+use config::Runfile;
 use config::ServiceRegistry;
+use container;
+use deps;
+use process;
+use service::Service;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 #[derive(Debug)]
 pub struct StopRunner<'a> {
@@ -43,10 +51,8 @@ impl<'a> Runnable<'a> for StopRunner<'a> {
     }
 
     fn run(&self) -> DmgrResult {
-        println!("Matches = {:#?}", self.args);
-
         match self.args {
-            //            a if a.is_present("all") => stop_all(a),
+            a if a.is_present("all") => stop_all(a),
             //            c if c.is_present("container") => stop_container(c),
             //            d if d.is_present("dev_mode") => stop_dev_mode(d),
             default => stop(default),
@@ -55,31 +61,133 @@ impl<'a> Runnable<'a> for StopRunner<'a> {
 }
 
 fn stop<'a>(args: &'a ArgMatches) -> DmgrResult {
-    let svc_name = args.value_of("service_or_group").unwrap();
-    let svc = ServiceRegistry::get()?.get_service(svc_name)?;
-    info!("stopping {:?}...", &svc.name);
+    let target = args.value_of("service_or_group").unwrap();
+    let registry = ServiceRegistry::get()?;
+    let names = registry.resolve(target)?;
 
-    let pid = svc.pid()?;
-    warn!("pid is {:?}", &pid);
+    if names.len() == 1 {
+        return stop_service(&registry.get_service(&names[0])?);
+    }
 
-    let system = sysinfo::System::new();
-    let p = system
-        .get_process(pid as i32)
-        .ok_or(dmgr_err!("could not find process with pid {:?}", pid))?;
-    //    info!("proc = {:?}", p);
+    info!("stopping group {:?}: {:?}", target, names);
+    command::report("stop", stop_in_order(&registry, &names))
+}
 
-    let sig = Signal::Continue;
-    let kill_result = p.kill(sig);
+fn stop_all<'a>(_args: &'a ArgMatches) -> DmgrResult {
+    let registry = ServiceRegistry::get()?;
+    let (services, broken) = registry.services();
+    let names: Vec<String> = services.into_iter().map(|s| s.name).collect();
 
-    info!("kill result = {:?}", kill_result);
+    info!("stopping all services...");
+    let mut results = stop_in_order(&registry, &names);
+    results.extend(broken.into_iter().map(|(name, e)| (name, Err(e))));
+    command::report("stop", results)
+}
+
+// Stops services in the reverse of their start order, so nothing is stopped
+// while a service that depends on it is still running
+fn stop_in_order(registry: &ServiceRegistry, names: &[String]) -> Vec<(String, Outcome)> {
+    let mut order = deps::order_among(registry, names);
+    order.reverse();
+
+    order
+        .into_iter()
+        .map(|svc| {
+            let result = if svc.is_running() {
+                stop_service(&svc).map(|_| "stopped")
+            } else {
+                Ok("not running")
+            };
+            (svc.name, result)
+        })
+        .collect()
+}
+
+pub fn stop_service(svc: &Service) -> DmgrResult {
+    info!("stopping {:?}...", &svc.name);
+
+    let runfile = svc.runfile()?;
+    if runfile.is_container {
+        stop_container(svc, &runfile)?;
+    } else {
+        let pid = svc.pid()?;
+        // e.g. after a crash or reboot; its pid may be another process's now
+        if !process::is_same_process(pid, runfile.started) {
+            warn!(
+                "pid {} is no longer {:?}'s process, removing its stale runfile",
+                pid, &svc.name
+            );
+            fs::remove_file(svc.run_file()?)?;
+            return Ok(());
+        }
+        stop_process(svc, pid)?;
+    }
+
+    ensure_ports_closed(svc)?;
+    fs::remove_file(svc.run_file()?)?;
+    info!("stopped {:?}", &svc.name);
 
     Ok(())
 }
 
-//fn stop_all<'a>(args: &'a ArgMatches) -> DmgrResult {
-//    Ok(())
-//}
-//
-//fn stop_group(name: &str) -> DmgrResult {
-//    Ok(())
-//}
+// Sends SIGTERM to the process and everything it spawned, then SIGKILL if
+// any of them outlive the grace period
+pub fn stop_process(svc: &Service, pid: i32) -> DmgrResult {
+    if !process::tree_is_alive(pid) {
+        warn!("{:?} process {} is not running", &svc.name, pid);
+        return Ok(());
+    }
+
+    info!("sending SIGTERM to {:?} (pid {})", &svc.name, pid);
+    process::terminate_tree(pid);
+    if process::wait_for_exit(pid, svc.stop_grace_period) {
+        return Ok(());
+    }
+
+    warn!(
+        "{:?} still running after {}s, sending SIGKILL",
+        &svc.name,
+        svc.stop_grace_period.as_secs()
+    );
+    process::kill_tree(pid);
+    if process::wait_for_exit(pid, svc.kill_timeout) {
+        return Ok(());
+    }
+
+    fail!("could not kill {:?} (pid {})", &svc.name, pid)
+}
+
+// Listening sockets can take a moment to close after their process exits.
+// The runfile is kept if they never do, since something still holds them.
+fn ensure_ports_closed(svc: &Service) -> DmgrResult {
+    let started = Instant::now();
+
+    loop {
+        let open = svc.open_ports();
+        if open.is_empty() {
+            return Ok(());
+        }
+
+        if started.elapsed() >= svc.kill_timeout {
+            fail!(
+                "{:?} stopped but ports {:?} are still open",
+                &svc.name,
+                open
+            )
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn stop_container(svc: &Service, runfile: &Runfile) -> DmgrResult {
+    let id = runfile
+        .container_id
+        .as_ref()
+        .ok_or(dmgr_err!("no container id recorded for {:?}", &svc.name))?;
+    let runtime = container::runtime_for(runfile);
+
+    runtime.stop(id)?;
+    info!("stopped container {} with {}", id, runtime.name());
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use capture;
+use command::start;
+use command::DmgrResult;
+use command::{Runnable, Subcommand};
+use config::CaptureMode;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// Spawned by `start` for services with output capture turned on; not meant to
+// be run by hand
+#[derive(Debug)]
+pub struct SuperviseRunner<'a> {
+    pub args: &'a ArgMatches<'a>,
+}
+
+impl<'a> Subcommand for SuperviseRunner<'a> {
+    const NAME: &'static str = "supervise";
+
+    fn sub_cmd() -> App<'static, 'static> {
+        SubCommand::with_name(Self::NAME)
+            .about("run a command, writing its timestamped output to a log")
+            .setting(AppSettings::Hidden)
+            .arg(
+                Arg::with_name("capture")
+                    .help("format of the captured lines")
+                    .long("capture")
+                    .value_name("MODE")
+                    .possible_values(&CaptureMode::names())
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("log")
+                    .help("file to append output to")
+                    .long("log")
+                    .value_name("PATH")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("command")
+                    .help("command to run")
+                    .required(true),
+            )
+    }
+}
+
+impl<'a> Runnable<'a> for SuperviseRunner<'a> {
+    fn new(args: &'a ArgMatches) -> Self {
+        SuperviseRunner { args }
+    }
+
+    fn run(&self) -> DmgrResult {
+        let mode = CaptureMode::from_str(self.args.value_of("capture").unwrap())?;
+        let log = PathBuf::from(self.args.value_of("log").unwrap());
+        let cmd = start::cmd_for(self.args.value_of("command").unwrap())?;
+
+        // exit as the command did, so `start` can tell whether it failed
+        let status = capture::supervise(cmd, &log, mode)?;
+        if !status.success() {
+            std::process::exit(status.code().unwrap_or(1))
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,424 @@
+extern crate chrono;
+
+use self::chrono::Local;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sysinfo::SystemExt;
+
+use capture;
+use color;
+use command::list::{Column, ListEntry};
+use command::restart;
+use command::start;
+use command::start::{StartOptions, WaitOptions};
+use command::stop;
+use command::DmgrResult;
+use command::Outcome;
+use command::{Runnable, Subcommand};
+use config::ServiceRegistry;
+use constants;
+use logfile;
+use service::Service;
+use settings::Settings;
+use status;
+use status::State;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use terminal;
+use terminal::{Key, RawMode};
+
+const COLUMNS: [Column; 6] = [
+    Column::Status,
+    Column::Pid,
+    Column::Uptime,
+    Column::Cpu,
+    Column::Mem,
+    Column::OpenPorts,
+];
+const MAX_EVENTS: usize = 5;
+const LOG_LINES: usize = 10;
+const KEYS: &str = "up/down select  s start  x stop  r restart  l logs  q quit";
+
+#[derive(Debug)]
+pub struct TopRunner<'a> {
+    pub args: &'a ArgMatches<'a>,
+}
+
+impl<'a> Subcommand for TopRunner<'a> {
+    const NAME: &'static str = "top";
+
+    fn sub_cmd() -> App<'static, 'static> {
+        SubCommand::with_name(Self::NAME)
+            .about("shows a live view of services, highlighting the ones whose status changes")
+            .alias("watch")
+            .arg(
+                Arg::with_name("service_or_group")
+                    .help("services or groups to show, instead of all of them")
+                    .multiple(true),
+            )
+            .arg(
+                Arg::with_name("interval")
+                    .help("seconds between refreshes")
+                    .long("interval")
+                    .short("n")
+                    .value_name("SECONDS")
+                    .default_value(constants::DEFAULT_TOP_INTERVAL_SECS),
+            )
+            .arg(
+                Arg::with_name("timeout")
+                    .help(
+                        "milliseconds to wait for a service's status before showing it as unknown",
+                    )
+                    .long("timeout")
+                    .value_name("MILLIS")
+                    .default_value(constants::DEFAULT_STATUS_TIMEOUT_MILLIS),
+            )
+            .arg(
+                Arg::with_name("interactive")
+                    .help(
+                        "select services with the arrow keys to start, stop, restart or tail them",
+                    )
+                    .long("interactive")
+                    .short("i"),
+            )
+    }
+}
+
+impl<'a> Runnable<'a> for TopRunner<'a> {
+    fn new(args: &'a ArgMatches) -> Self {
+        TopRunner { args }
+    }
+
+    fn run(&self) -> DmgrResult {
+        let registry = ServiceRegistry::get()?;
+        let services = match self.args.values_of("service_or_group") {
+            Some(targets) => {
+                let mut services = vec![];
+                for name in &registry.resolve_all(targets)? {
+                    services.push(registry.get_service(name)?);
+                }
+                services
+            }
+            None => registry.loadable_services(),
+        };
+
+        let interval = Duration::from_secs(u64::from_str(self.args.value_of("interval").unwrap())?);
+        let timeout = Duration::from_millis(u64::from_str(self.args.value_of("timeout").unwrap())?);
+        let mut dashboard = Dashboard::new(services, timeout, interval);
+
+        if self.args.is_present("interactive") {
+            dashboard.interact()
+        } else {
+            dashboard.watch()
+        }
+    }
+}
+
+struct Dashboard {
+    services: Vec<Service>,
+    timeout: Duration,
+    interval: Duration,
+    // kept between refreshes, as CPU use is measured from one to the next
+    system: Arc<sysinfo::System>,
+    entries: Vec<ListEntry>,
+    states: HashMap<String, State>,
+    // when each service last changed status, and what it changed from
+    changes: HashMap<String, (State, Instant)>,
+    events: Vec<String>,
+    interactive: bool,
+    selected: usize,
+    show_logs: bool,
+    message: Option<String>,
+}
+
+impl Dashboard {
+    fn new(services: Vec<Service>, timeout: Duration, interval: Duration) -> Self {
+        Dashboard {
+            services,
+            timeout,
+            interval,
+            system: Arc::new(sysinfo::System::new()),
+            entries: vec![],
+            states: HashMap::new(),
+            changes: HashMap::new(),
+            events: vec![],
+            interactive: false,
+            selected: 0,
+            show_logs: false,
+            message: None,
+        }
+    }
+
+    fn watch(&mut self) -> DmgrResult {
+        loop {
+            self.refresh();
+            self.draw()?;
+            thread::sleep(self.interval);
+        }
+    }
+
+    // Keys are handled as they come; the statuses are still only probed
+    // once per interval, or right after an action finishes
+    fn interact(&mut self) -> DmgrResult {
+        let term = RawMode::enable()?;
+        let (tx, rx) = mpsc::channel();
+        self.interactive = true;
+
+        loop {
+            self.refresh();
+            self.draw()?;
+
+            let next = Instant::now() + self.interval;
+            while Instant::now() < next {
+                if let Ok(message) = rx.try_recv() {
+                    self.message = Some(message);
+                    break;
+                }
+
+                match term.read_key()? {
+                    None => continue,
+                    Some(Key::Interrupt) | Some(Key::Char('q')) => return Ok(()),
+                    Some(Key::Up) | Some(Key::Char('k')) => {
+                        self.selected = self.selected.saturating_sub(1)
+                    }
+                    Some(Key::Down) | Some(Key::Char('j')) => {
+                        if self.selected + 1 < self.services.len() {
+                            self.selected += 1
+                        }
+                    }
+                    Some(Key::Char('l')) => self.show_logs = !self.show_logs,
+                    Some(Key::Char(c)) => match Action::for_key(c) {
+                        Some(action) => self.act(action, tx.clone()),
+                        None => continue,
+                    },
+                }
+                self.draw()?;
+            }
+        }
+    }
+
+    fn refresh(&mut self) {
+        // probes still running past an earlier timeout hold on to the old table
+        match Arc::get_mut(&mut self.system) {
+            Some(system) => system.refresh_processes(),
+            None => self.system = Arc::new(sysinfo::System::new()),
+        }
+
+        let snapshots = status::probe_all(&self.services, self.system.clone(), self.timeout);
+        self.entries = self
+            .services
+            .iter()
+            .zip(snapshots)
+            .map(|(svc, snapshot)| ListEntry::new(svc, snapshot, &self.system, true))
+            .collect();
+
+        let now = Instant::now();
+        for entry in &self.entries {
+            let previous = self.states.insert(entry.name.clone(), entry.status);
+            match previous {
+                Some(from) if from != entry.status => {
+                    self.changes.insert(entry.name.clone(), (from, now));
+                    self.events.push(format!(
+                        "{} {}: {} -> {}",
+                        Local::now().format("%H:%M:%S"),
+                        entry.name,
+                        from.as_str(),
+                        entry.status.as_str()
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        let excess = self.events.len().saturating_sub(MAX_EVENTS);
+        self.events.drain(..excess);
+    }
+
+    // Runs the action in the background, so the view keeps updating meanwhile
+    fn act(&mut self, action: Action, tx: Sender<String>) {
+        let svc = match self.services.get(self.selected) {
+            Some(svc) => svc.clone(),
+            None => return,
+        };
+
+        self.message = Some(format!("{} {}...", action.name(), svc.name));
+        thread::spawn(move || {
+            let message = match action.run(&svc) {
+                Ok(outcome) => format!("{} {}: {}", action.name(), svc.name, outcome),
+                Err(e) => format!("{} {} failed: {}", action.name(), svc.name, e),
+            };
+            let _ = tx.send(message);
+        });
+    }
+
+    fn draw(&self) -> DmgrResult {
+        print!("{}{}", terminal::CLEAR, self.frame().join("\n"));
+        io::stdout().flush()?;
+        Ok(())
+    }
+
+    fn frame(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "every {}s: {}",
+            self.interval.as_secs(),
+            Local::now().format("%H:%M:%S")
+        )];
+        if self.interactive {
+            lines.push(KEYS.to_string());
+        }
+        lines.push(String::new());
+        lines.extend(self.table());
+
+        if !self.events.is_empty() {
+            lines.push(String::new());
+            lines.extend(self.events.iter().cloned());
+        }
+
+        if let Some(ref message) = self.message {
+            lines.push(String::new());
+            lines.push(message.clone());
+        }
+
+        if self.show_logs {
+            if let Some(svc) = self.services.get(self.selected) {
+                lines.push(String::new());
+                lines.extend(log_tail(svc));
+            }
+        }
+
+        lines
+    }
+
+    // The columns are padded by hand, as color codes and raw mode both
+    // throw off the usual table
+    fn table(&self) -> Vec<String> {
+        let mut header = vec!["Service".to_string()];
+        header.extend(COLUMNS.iter().map(|c| c.header().to_string()));
+        let mut rows = vec![header];
+        for entry in &self.entries {
+            let mut row = vec![entry.name.clone()];
+            row.extend(COLUMNS.iter().map(|c| c.cell(entry)));
+            rows.push(row);
+        }
+
+        let mut widths = vec![0; rows[0].len()];
+        for row in &rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+
+        rows.iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let text = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, &w)| format!("{:1$}", cell, w))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                let marker = if self.interactive && i == self.selected + 1 {
+                    "> "
+                } else {
+                    "  "
+                };
+
+                let highlight = match i {
+                    0 => None,
+                    _ => self.highlight(&self.entries[i - 1]),
+                };
+                match highlight {
+                    Some(c) => format!("{}{}", marker, color::paint(text.trim_end(), c)),
+                    None => format!("{}{}", marker, text.trim_end()),
+                }
+            })
+            .collect()
+    }
+
+    // Recently changed services are colored by what they changed to
+    fn highlight(&self, entry: &ListEntry) -> Option<u8> {
+        let highlight_for = Duration::from_secs(constants::TRANSITION_HIGHLIGHT_SECS);
+        match self.changes.get(&entry.name) {
+            Some(&(_, at)) if at.elapsed() < highlight_for => Some(match entry.status {
+                State::Ready => color::GREEN,
+                State::Waiting => color::YELLOW,
+                State::Stopped => color::RED,
+                State::Unknown => color::MAGENTA,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Start,
+    Stop,
+    Restart,
+}
+
+impl Action {
+    fn for_key(key: char) -> Option<Self> {
+        match key {
+            's' => Some(Action::Start),
+            'x' => Some(Action::Stop),
+            'r' => Some(Action::Restart),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Action::Start => "start",
+            Action::Stop => "stop",
+            Action::Restart => "restart",
+        }
+    }
+
+    // Doesn't wait for readiness, as the view shows that anyway
+    fn run(&self, svc: &Service) -> Outcome {
+        let readiness = WaitOptions {
+            timeout: Settings::get()?.start_timeout,
+            interval: Duration::from_millis(u64::from_str(
+                constants::DEFAULT_POLL_INTERVAL_MILLIS,
+            )?),
+        };
+        let opts = StartOptions::from_runfile(svc, readiness, true);
+
+        match *self {
+            Action::Start if svc.is_running() => Ok("already running"),
+            Action::Start => start::start_service(svc, &opts).map(|_| "started"),
+            Action::Stop if !svc.is_running() => Ok("not running"),
+            Action::Stop => stop::stop_service(svc).map(|_| "stopped"),
+            Action::Restart => restart::restart(svc, &opts).map(|_| "restarted"),
+        }
+    }
+}
+
+// The end of the service's log, cut to the width of the terminal
+fn log_tail(svc: &Service) -> Vec<String> {
+    let lines = match svc
+        .log_file()
+        .and_then(|path| logfile::tail(&path, LOG_LINES))
+    {
+        Ok(lines) => lines,
+        Err(e) => return vec![format!("no logs for {}: {}", svc.name, e)],
+    };
+    let width = terminal::size().map_or(usize::max_value(), |(cols, _)| cols);
+
+    lines
+        .iter()
+        .map(|line| match capture::parse_line(line) {
+            Some(captured) => captured.render(),
+            None => line.clone(),
+        })
+        .map(|line| line.chars().take(width).collect())
+        .collect()
+}
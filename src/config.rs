@@ -1,37 +1,72 @@
-extern crate home;
 extern crate serde_derive;
 extern crate serde_json;
+extern crate serde_yaml;
 extern crate toml;
 
 use self::serde_derive::{Deserialize, Serialize};
 
+use log::{info, warn};
+
 use std::collections::btree_map::BTreeMap;
+use std::fs;
+use std::fs::create_dir_all;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::string::String;
 
+use command::DmgrErr;
 use command::DmgrResult;
 use constants;
+use migrations;
 use service::Service;
+use settings::Settings;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ServiceRegistry {
     pub path: PathBuf,
     pub content: ServiceRegistryContent,
+    pub groups: ServiceGroups,
+    // as read from disk, before any migrations
+    pub schema_version: u32,
+}
+
+// The registry as saved. New fields need a serde default, so registries that
+// predate them still load; anything that moves or changes shape needs a
+// migration instead.
+#[derive(Debug, Deserialize, Serialize)]
+struct RegistryFile {
+    schema_version: u32,
+    #[serde(default)]
+    services: ServiceRegistryContent,
+    #[serde(default)]
+    groups: ServiceGroups,
 }
 
 pub type ServiceRegistryContent = BTreeMap<String, ServiceRegistryEntryJson>;
 
+// group name -> names of its member services
+pub type ServiceGroups = BTreeMap<String, Vec<String>>;
+
 impl ServiceRegistry {
     pub fn get() -> DmgrResult<ServiceRegistry> {
-        Self::from_path(Self::path())
+        Self::at(Settings::get()?.registry.clone())
     }
 
-    fn path() -> PathBuf {
-        PathBuf::from(home::home_dir().unwrap())
-            .join(constants::SERVICE_CONFIG_DIR)
-            .join(constants::SERVICE_REGISTRY_FILENAME)
+    // Creates an empty registry the first time round
+    fn at(path: PathBuf) -> DmgrResult<ServiceRegistry> {
+        if path.exists() {
+            return Self::from_path(path);
+        }
+
+        ServiceRegistry {
+            path,
+            content: ServiceRegistryContent::new(),
+            groups: ServiceGroups::new(),
+            schema_version: constants::REGISTRY_SCHEMA_VERSION,
+        }
+        .save()
     }
 
     fn from_path(path: PathBuf) -> DmgrResult<Self> {
@@ -39,11 +74,9 @@ impl ServiceRegistry {
         path.to_str().map_or(e, |s| Self::from(s))
     }
 
+    // The registry is always JSON, whatever --registry names it
     pub fn from(path: &str) -> DmgrResult<Self> {
-        match path {
-            json if json.ends_with(".json") => ServiceRegistry::from_json(json),
-            _ => fail!("could not read '{:?}'", path),
-        }
+        ServiceRegistry::from_json(path)
     }
 
     pub fn get_service(&self, name: &str) -> DmgrResult<Service> {
@@ -51,16 +84,132 @@ impl ServiceRegistry {
             .content
             .get(name)
             .ok_or(dmgr_err!("unable to find service {:?}", name))?;
-        let cfg_path = entry.repo_path.join(".solo").join(format!("{}.json", name));
-        Service::from_path(&cfg_path)
+        let cfg_dir = entry.repo_path.join(constants::SERVICE_CONFIG_DIR);
+        let mut svc = Service::from_path(&find_service_config(&cfg_dir, name)?)?;
+
+        // a tag pinned in the registry wins over the one in the service config
+        if entry.image_tag.is_some() {
+            svc.image_tag = entry.image_tag.clone();
+        }
+
+        Ok(svc)
     }
 
-    pub fn services(&self) -> Vec<Service> {
-        self.content
-            .keys()
-            .into_iter()
-            .filter_map(|t| self.get_service(t).ok())
-            .collect()
+    // Resolves a service or group name to the names of the services it covers
+    pub fn resolve(&self, name: &str) -> DmgrResult<Vec<String>> {
+        if self.content.contains_key(name) {
+            return Ok(vec![name.to_string()]);
+        }
+
+        match self.groups.get(name) {
+            Some(members) if members.is_empty() => fail!("group {:?} has no services", name),
+            Some(members) => Ok(members.clone()),
+            None => fail!("unable to find service or group {:?}", name),
+        }
+    }
+
+    // Like `resolve`, for several names at once, without duplicates
+    pub fn resolve_all<'a, I>(&self, names: I) -> DmgrResult<Vec<String>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut resolved: Vec<String> = vec![];
+        for name in names {
+            for svc in self.resolve(name)? {
+                if !resolved.contains(&svc) {
+                    resolved.push(svc);
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    pub fn add_to_group(mut self, group: &str, services: &[&str]) -> DmgrResult<Self> {
+        if self.content.contains_key(group) {
+            fail!("{:?} is already the name of a service", group)
+        }
+
+        if let Some(svc) = services.iter().find(|s| !self.content.contains_key(**s)) {
+            fail!("unable to find service {:?}", svc)
+        }
+
+        let members = self
+            .groups
+            .entry(group.to_string())
+            .or_insert_with(Vec::new);
+        for svc in services {
+            if !members.iter().any(|m| m == svc) {
+                members.push(svc.to_string());
+            }
+        }
+
+        Ok(self)
+    }
+
+    // Removes the given services from a group, or the whole group when no
+    // services are given. Groups left empty are removed too.
+    pub fn remove_from_group(mut self, group: &str, services: &[&str]) -> DmgrResult<Self> {
+        let now_empty = {
+            let members = self
+                .groups
+                .get_mut(group)
+                .ok_or(dmgr_err!("unable to find group {:?}", group))?;
+            if let Some(svc) = services.iter().find(|s| !members.iter().any(|m| m == *s)) {
+                fail!("{:?} is not in group {:?}", svc, group)
+            }
+
+            members.retain(|m| !services.is_empty() && !services.contains(&m.as_str()));
+            members.is_empty()
+        };
+
+        if now_empty {
+            self.groups.remove(group);
+        }
+
+        Ok(self)
+    }
+
+    // Removes a service, and it from every group, so no group names a
+    // service that isn't registered. Groups left empty are removed too.
+    pub fn remove_svc(mut self, name: &str) -> Self {
+        self.content.remove(name);
+        for members in self.groups.values_mut() {
+            members.retain(|m| m != name);
+        }
+
+        let empty: Vec<String> = self
+            .groups
+            .iter()
+            .filter(|&(_, members)| members.is_empty())
+            .map(|(group, _)| group.clone())
+            .collect();
+        for group in empty {
+            self.groups.remove(&group);
+        }
+        self
+    }
+
+    // Every registered service, apart from those whose config fails to
+    // load, which come back by name with the error
+    pub fn services(&self) -> (Vec<Service>, Vec<(String, DmgrErr)>) {
+        let mut services = vec![];
+        let mut broken = vec![];
+        for name in self.content.keys() {
+            match self.get_service(name) {
+                Ok(svc) => services.push(svc),
+                Err(e) => broken.push((name.clone(), e)),
+            }
+        }
+        (services, broken)
+    }
+
+    // Every service that loads, warning about the rest
+    pub fn loadable_services(&self) -> Vec<Service> {
+        let (services, broken) = self.services();
+        for (name, e) in broken {
+            warn!("could not load {:?}: {}", name, e);
+        }
+        services
     }
 
     pub fn add_svc(self, svc: &Service) -> DmgrResult<Self> {
@@ -88,11 +237,31 @@ impl ServiceRegistry {
         Ok(self)
     }
 
-    pub fn save(self) -> DmgrResult<Self> {
-        let mut file = File::create(Self::path())?;
-        let content = serde_json::to_string_pretty(&self.content)?;
+    // Always saves in the current schema, finishing any migration
+    pub fn save(mut self) -> DmgrResult<Self> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+
+        let registry = RegistryFile {
+            schema_version: constants::REGISTRY_SCHEMA_VERSION,
+            services: self.content.clone(),
+            groups: self.groups.clone(),
+        };
+        let mut content = serde_json::to_string_pretty(&registry)?;
+        content.push_str("\n");
+        File::create(&self.path)?.write_all(content.as_bytes())?;
+
+        if self.schema_version < constants::REGISTRY_SCHEMA_VERSION {
+            info!(
+                "upgraded {:?} from schema version {} to {}",
+                self.path,
+                self.schema_version,
+                constants::REGISTRY_SCHEMA_VERSION
+            );
+            self.schema_version = constants::REGISTRY_SCHEMA_VERSION;
+        }
 
-        file.write_all(content.as_bytes())?;
         Ok(self)
     }
 
@@ -101,10 +270,16 @@ impl ServiceRegistry {
         let mut file = File::open(path)?;
 
         file.read_to_string(&mut contents)?;
-        let content: ServiceRegistryContent = serde_json::from_str(contents.as_str())?;
+        let raw: serde_json::Value = serde_json::from_str(contents.as_str())?;
+        let path = PathBuf::from(path);
+        let schema_version = migrations::schema_version(&raw)?;
+        let registry: RegistryFile = serde_json::from_value(migrations::migrate(raw, &path)?)?;
+
         Ok(ServiceRegistry {
-            content,
-            path: PathBuf::from(path),
+            content: registry.services,
+            groups: registry.groups,
+            schema_version,
+            path,
         })
     }
 }
@@ -113,14 +288,26 @@ impl ServiceRegistry {
 pub struct ServiceConfigContent {
     pub aliases: Option<Vec<String>>,
     pub image_name: Option<String>,
+    pub image_tag: Option<String>,
     pub ports: Option<Vec<u16>>,
+    // address the service listens on, if not loopback
+    pub host: Option<String>,
     pub start_container: Option<String>,
     pub start_process: Option<String>,
     pub start_dev_mode: Option<String>,
-    pub http_check: Option<String>,
-    pub health_checks: Option<Vec<String>>,
+    pub depends_on: Option<Vec<String>>,
+    pub watch: Option<WatchConfigContent>,
+    pub http_check: Option<HttpCheckContent>,
+    pub health_checks: Option<Vec<HealthCheckContent>>,
+    pub log_rotation: Option<LogRotationContent>,
+    pub log_capture: Option<CaptureMode>,
+    pub stop_grace_period: Option<u64>,
+    pub kill_timeout: Option<u64>,
     pub register_by_default: Option<bool>,
     pub requires_sudo: Option<bool>,
+    // left out of `list` unless asked for with --all or --hidden
+    pub hidden: Option<bool>,
+    pub tags: Option<Vec<String>>,
 }
 
 impl ServiceConfigContent {
@@ -134,11 +321,187 @@ impl ServiceConfigContent {
         let mut file = File::open(path)?;
 
         file.read_to_string(&mut contents)?;
-        Ok(serde_json::from_str(&contents)?)
+        match path {
+            json if json.ends_with(".json") => Ok(serde_json::from_str(&contents)?),
+            toml if toml.ends_with(".toml") => Ok(toml::from_str(&contents)?),
+            yaml if yaml.ends_with(".yaml") || yaml.ends_with(".yml") => {
+                Ok(serde_yaml::from_str(&contents)?)
+            }
+            _ => fail!("could not read '{:?}'", path),
+        }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+// The config file for a service in a config dir, e.g. `.solo/api.toml`. When
+// there are several, the first of SERVICE_CONFIG_EXTENSIONS wins.
+pub fn find_service_config(cfg_dir: &PathBuf, name: &str) -> DmgrResult<PathBuf> {
+    constants::SERVICE_CONFIG_EXTENSIONS
+        .iter()
+        .map(|ext| cfg_dir.join(format!("{}.{}", name, ext)))
+        .find(|path| path.is_file())
+        .ok_or(dmgr_err!(
+            "unable to find a config file for service {:?} in {:?}",
+            name,
+            cfg_dir
+        ))
+}
+
+// Every service config in a config dir, one per service, with the same
+// precedence as `find_service_config`
+pub fn find_all_service_configs(cfg_dir: &PathBuf) -> DmgrResult<Vec<PathBuf>> {
+    let mut found: Vec<(String, usize, PathBuf)> = vec![];
+    for path in fs::read_dir(cfg_dir)?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+    {
+        let name = path.file_stem().map(|n| n.to_string_lossy().to_string());
+        let rank = path.extension().and_then(|ext| {
+            constants::SERVICE_CONFIG_EXTENSIONS
+                .iter()
+                .position(|e| *e == ext.to_string_lossy())
+        });
+        if let (Some(name), Some(rank)) = (name, rank) {
+            found.push((name, rank, path));
+        }
+    }
+    found.sort();
+
+    let mut configs: Vec<PathBuf> = vec![];
+    for (i, (name, _, path)) in found.iter().enumerate() {
+        match i.checked_sub(1).map(|prev| &found[prev]) {
+            Some(&(ref prev_name, _, _)) if prev_name == name => {
+                warn!(
+                    "ignoring {:?}, as another config for {:?} takes precedence",
+                    path, name
+                )
+            }
+            _ => configs.push(path.clone()),
+        }
+    }
+
+    Ok(configs
+        .into_iter()
+        .filter_map(|path| path.canonicalize().ok())
+        .collect())
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct WatchConfigContent {
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub interval_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LogRotationContent {
+    pub max_size: Option<ByteSize>,
+    pub keep: Option<usize>,
+    pub compress: Option<bool>,
+}
+
+// A plain string is just the path to GET
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum HttpCheckContent {
+    Path(String),
+    Full(HttpCheck),
+}
+
+impl From<HttpCheckContent> for HttpCheck {
+    fn from(content: HttpCheckContent) -> Self {
+        match content {
+            HttpCheckContent::Path(path) => HttpCheck {
+                path,
+                port: None,
+                headers: BTreeMap::new(),
+                accept: vec![],
+                body: None,
+                max_redirects: None,
+                timeout_ms: None,
+            },
+            HttpCheckContent::Full(check) => check,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HttpCheck {
+    pub path: String,
+    // tries each of the service's ports when not given
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // e.g. [200, "3xx"]; anything below 400 passes when empty
+    #[serde(default)]
+    pub accept: Vec<StatusPattern>,
+    // regular expression the response body must match
+    pub body: Option<String>,
+    // 0 turns off following redirects
+    pub max_redirects: Option<usize>,
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum StatusPattern {
+    Code(u16),
+    Class(String),
+}
+
+// Plain strings are what `health_checks` held before checks had types, and
+// were never run. They are now run as shell commands that must exit 0, and a
+// service whose config still has them warns about it when loaded
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum HealthCheckContent {
+    Command(String),
+    Typed(HealthCheck),
+}
+
+impl From<HealthCheckContent> for HealthCheck {
+    fn from(content: HealthCheckContent) -> Self {
+        match content {
+            HealthCheckContent::Command(command) => HealthCheck::Command {
+                command,
+                exit_code: None,
+            },
+            HealthCheckContent::Typed(check) => check,
+        }
+    }
+}
+
+// One of the checks that must all pass before a service counts as ready,
+// tagged by its `type`. File paths and commands are relative to the service's
+// repo, and log line patterns are regular expressions
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HealthCheck {
+    Tcp {
+        port: u16,
+        host: Option<String>,
+    },
+    Http(HttpCheck),
+    Command {
+        command: String,
+        exit_code: Option<i32>,
+    },
+    File {
+        path: PathBuf,
+    },
+    LogLine {
+        pattern: String,
+    },
+}
+
+// Either a number of bytes or a string like "100M"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ByteSize {
+    Bytes(u64),
+    Human(String),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ServiceRegistryEntryJson {
     #[serde(skip_serializing_if = "Option::is_none")]
     aliases: Option<Vec<String>>,
@@ -159,8 +522,254 @@ impl From<Service> for ServiceRegistryEntryJson {
 
 pub type Pid = i32;
 
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartMode {
+    Process,
+    DevMode,
+    Container,
+}
+
+impl StartMode {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            StartMode::Process => "process",
+            StartMode::DevMode => "dev_mode",
+            StartMode::Container => "container",
+        }
+    }
+}
+
+impl Default for StartMode {
+    fn default() -> Self {
+        StartMode::Process
+    }
+}
+
+// How a service's output gets into its log file: written directly, or through
+// a supervisor that tags each line with a timestamp and its stream
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureMode {
+    Raw,
+    Text,
+    Json,
+}
+
+impl CaptureMode {
+    pub const ALL: [CaptureMode; 3] = [CaptureMode::Raw, CaptureMode::Text, CaptureMode::Json];
+
+    // For the --capture flags
+    pub fn names() -> Vec<&'static str> {
+        Self::ALL.iter().map(|m| m.as_str()).collect()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            CaptureMode::Raw => "raw",
+            CaptureMode::Text => "text",
+            CaptureMode::Json => "json",
+        }
+    }
+}
+
+impl Default for CaptureMode {
+    fn default() -> Self {
+        CaptureMode::Raw
+    }
+}
+
+impl FromStr for CaptureMode {
+    type Err = DmgrErr;
+
+    fn from_str(s: &str) -> DmgrResult<Self> {
+        match Self::ALL.iter().find(|m| m.as_str() == s) {
+            Some(mode) => Ok(*mode),
+            None => fail!(
+                "unknown capture mode {:?}; use {}",
+                s,
+                Self::names().join(", ")
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Runfile {
-    pub pid: Pid,
+    // unknown when a container's runtime couldn't report it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<Pid>,
+    // of the process, as process::start_time reports it, so a later process
+    // given the same pid isn't taken for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started: Option<u64>,
     pub is_container: bool,
+    #[serde(default)]
+    pub mode: StartMode,
+    // the exact command the service was started with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub capture: CaptureMode,
+    // size of the log when the service was spawned; log_line checks only
+    // look at what was written after it
+    #[serde(default)]
+    pub log_offset: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_runtime: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scratch::ScratchDir;
+
+    fn read(path: &PathBuf) -> serde_json::Value {
+        serde_json::from_reader(File::open(path).unwrap()).unwrap()
+    }
+
+    fn touch(dir: &ScratchDir, files: &[&str]) {
+        for file in files {
+            dir.write(file, "{}");
+        }
+    }
+
+    #[test]
+    fn finds_configs_by_extension_precedence() {
+        let dir = ScratchDir::new("config-precedence");
+        touch(&dir, &["api.yml", "api.toml", "web.yaml", "web.yml"]);
+
+        assert_eq!(
+            find_service_config(dir.path(), "api").unwrap(),
+            dir.join("api.toml")
+        );
+        assert_eq!(
+            find_service_config(dir.path(), "web").unwrap(),
+            dir.join("web.yaml")
+        );
+        assert!(find_service_config(dir.path(), "db").is_err());
+    }
+
+    #[test]
+    fn finds_one_config_per_service() {
+        let scratch = ScratchDir::new("config-all");
+        touch(
+            &scratch,
+            &[
+                "api.yml",
+                "api.json",
+                "db.toml",
+                "web.yml",
+                "notes.txt",
+                "README",
+            ],
+        );
+        let dir = scratch.path().canonicalize().unwrap();
+
+        assert_eq!(
+            find_all_service_configs(&dir).unwrap(),
+            vec![
+                dir.join("api.json"),
+                dir.join("db.toml"),
+                dir.join("web.yml")
+            ]
+        );
+    }
+
+    #[test]
+    fn unregistered_services_leave_their_groups() {
+        let dir = ScratchDir::new("config-unregister");
+        let mut registry = ServiceRegistry {
+            path: dir.join("service-registry.json"),
+            content: ServiceRegistryContent::new(),
+            groups: ServiceGroups::new(),
+            schema_version: constants::REGISTRY_SCHEMA_VERSION,
+        };
+        for name in &["api", "db"] {
+            let cfg = dir.write(format!("{}/.solo/{}.json", name, name), "{}");
+            registry = registry.add_cfg(&cfg).unwrap();
+        }
+        let registry = registry
+            .add_to_group("backend", &["api", "db"])
+            .unwrap()
+            .add_to_group("data", &["db"])
+            .unwrap();
+
+        let registry = registry.remove_svc("db");
+        assert!(!registry.content.contains_key("db"));
+        assert_eq!(registry.resolve("backend").unwrap(), vec!["api"]);
+        assert!(registry.resolve("data").is_err());
+        assert!(!registry.groups.contains_key("data"));
+    }
+
+    #[test]
+    fn reads_legacy_health_checks_as_commands() {
+        let content: ServiceConfigContent = serde_json::from_str(
+            r#"{ "health_checks": ["curl -f localhost", { "type": "file", "path": "ready" }] }"#,
+        )
+        .unwrap();
+        let checks: Vec<HealthCheck> = content
+            .health_checks
+            .unwrap()
+            .into_iter()
+            .map(HealthCheck::from)
+            .collect();
+
+        match checks[0] {
+            HealthCheck::Command {
+                ref command,
+                exit_code: None,
+            } => assert_eq!(command, "curl -f localhost"),
+            ref other => panic!("expected a command check, got {:?}", other),
+        }
+        match checks[1] {
+            HealthCheck::File { ref path } => assert_eq!(path, &PathBuf::from("ready")),
+            ref other => panic!("expected a file check, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn creates_the_registry_on_first_use() {
+        let dir = ScratchDir::new("config-bootstrap");
+        let path = dir.join("nested/service-registry.json");
+
+        let registry = ServiceRegistry::at(path.clone()).unwrap();
+        assert!(registry.content.is_empty() && registry.groups.is_empty());
+        assert_eq!(
+            read(&path)["schema_version"],
+            constants::REGISTRY_SCHEMA_VERSION
+        );
+
+        let reloaded = ServiceRegistry::at(path).unwrap();
+        assert_eq!(reloaded.schema_version, constants::REGISTRY_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn reads_registries_without_a_json_extension() {
+        let dir = ScratchDir::new("config-named");
+        let path = dir.join("registry");
+
+        ServiceRegistry::at(path.clone()).unwrap();
+        let reloaded = ServiceRegistry::at(path).unwrap();
+        assert_eq!(reloaded.schema_version, constants::REGISTRY_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn upgrades_bare_registries_when_saved() {
+        let dir = ScratchDir::new("config-upgrade");
+        let path = dir.write(
+            "service-registry.json",
+            r#"{ "api": { "repo_path": "/src/api" } }"#,
+        );
+
+        let registry = ServiceRegistry::at(path.clone()).unwrap();
+        assert_eq!(registry.schema_version, 1);
+        assert_eq!(registry.content["api"].repo_path, PathBuf::from("/src/api"));
+
+        let saved = registry.save().unwrap();
+        assert_eq!(saved.schema_version, constants::REGISTRY_SCHEMA_VERSION);
+        assert_eq!(read(&path)["services"]["api"]["repo_path"], "/src/api");
+    }
 }
@@ -1,2 +1,39 @@
 pub const SERVICE_CONFIG_DIR: &str = ".solo";
 pub const SERVICE_REGISTRY_FILENAME: &str = "service-registry.json";
+pub const HOME_ENV_VAR: &str = "DMGR_HOME";
+pub const SETTINGS_FILENAME: &str = "dmgr.toml";
+pub const REGISTRY_ENV_VAR: &str = "DMGR_REGISTRY";
+pub const REGISTRY_SCHEMA_VERSION: u32 = 2;
+// in order of precedence, for services with more than one config file
+pub const SERVICE_CONFIG_EXTENSIONS: &[&str] = &["json", "toml", "yaml", "yml"];
+
+pub const DEFAULT_START_TIMEOUT_SECS: u64 = 60;
+pub const DEFAULT_POLL_INTERVAL_MILLIS: &str = "500";
+pub const DEFAULT_WATCH_INTERVAL_MILLIS: u64 = 1000;
+// matched against each directory before it is walked, at any depth
+pub const DEFAULT_WATCH_EXCLUDES: &[&str] = &["**/.git", "**/target", "**/node_modules"];
+pub const CONTAINER_RUNTIME_ENV_VAR: &str = "DMGR_CONTAINER_RUNTIME";
+pub const DEFAULT_CONTAINER_RUNTIME: &str = "docker";
+pub const DEFAULT_IMAGE_TAG: &str = "latest";
+pub const DEFAULT_STOP_GRACE_PERIOD_SECS: u64 = 10;
+pub const DEFAULT_KILL_TIMEOUT_SECS: u64 = 5;
+pub const START_TIMEOUT_ENV_VAR: &str = "DMGR_START_TIMEOUT";
+pub const STOP_GRACE_PERIOD_ENV_VAR: &str = "DMGR_STOP_GRACE_PERIOD";
+pub const LOG_DIR_ENV_VAR: &str = "DMGR_LOG_DIR";
+pub const COLOR_ENV_VAR: &str = "DMGR_COLOR";
+pub const LIST_COLUMNS_ENV_VAR: &str = "DMGR_LIST_COLUMNS";
+pub const LOG_LEVEL_ENV_VAR: &str = "DMGR_LOG_LEVEL";
+pub const LOG_MAX_SIZE_ENV_VAR: &str = "DMGR_LOG_MAX_SIZE";
+pub const LOG_KEEP_ENV_VAR: &str = "DMGR_LOG_KEEP";
+pub const LOG_COMPRESS_ENV_VAR: &str = "DMGR_LOG_COMPRESS";
+pub const DEFAULT_LOG_MAX_SIZE: u64 = 100 * 1024 * 1024;
+pub const DEFAULT_LOG_KEEP: usize = 5;
+pub const DEFAULT_LOG_LINES: usize = 10;
+pub const DEFAULT_HTTP_TIMEOUT_MILLIS: u64 = 2000;
+pub const DEFAULT_HTTP_MAX_REDIRECTS: usize = 5;
+pub const PORT_PROBE_TIMEOUT_MILLIS: u64 = 250;
+pub const DEFAULT_STATUS_TIMEOUT_MILLIS: &str = "800";
+pub const DEFAULT_PROBE_HOSTS: &[&str] = &["127.0.0.1", "::1"];
+pub const CPU_SAMPLE_MILLIS: u64 = 250;
+pub const DEFAULT_TOP_INTERVAL_SECS: &str = "2";
+pub const TRANSITION_HIGHLIGHT_SECS: u64 = 10;
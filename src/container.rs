@@ -0,0 +1,299 @@
+// Runs services as containers through a docker-compatible CLI
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::str::FromStr;
+
+use command::DmgrResult;
+use config::Pid;
+use config::Runfile;
+use constants;
+use service::Service;
+
+pub type ContainerId = String;
+
+pub trait ContainerRuntime {
+    fn name(&self) -> &str;
+
+    // Path or name of the runtime executable, recorded in the runfile so the
+    // same runtime is used to stop the container later
+    fn program(&self) -> &PathBuf;
+
+    fn command(&self, args: &[String]) -> Command;
+
+    fn pull(&self, image: &str) -> DmgrResult;
+
+    fn run(&self, args: &[String]) -> DmgrResult<ContainerId>;
+
+    fn stop(&self, id: &str) -> DmgrResult;
+
+    fn is_running(&self, id: &str) -> DmgrResult<bool>;
+
+    // On the host running the containers, which may be a VM, e.g. with
+    // Docker Desktop, so it's only a hint
+    fn pid(&self, id: &str) -> DmgrResult<Pid>;
+}
+
+#[derive(Debug, Clone)]
+pub struct CliRuntime {
+    name: String,
+    program: PathBuf,
+}
+
+impl CliRuntime {
+    pub fn docker() -> Self {
+        CliRuntime {
+            name: String::from("docker"),
+            program: PathBuf::from("docker"),
+        }
+    }
+
+    pub fn podman() -> Self {
+        CliRuntime {
+            name: String::from("podman"),
+            program: PathBuf::from("podman"),
+        }
+    }
+
+    // Any executable that speaks the docker CLI, e.g. a fake one in tests
+    pub fn from_program(program: PathBuf) -> Self {
+        let name = program
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("container runtime")
+            .to_string();
+        CliRuntime { name, program }
+    }
+
+    fn exec(&self, args: &[String]) -> DmgrResult<String> {
+        let output = self
+            .command(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| dmgr_err!("could not run {:?}: {}", &self.program, e))?;
+
+        if !output.status.success() {
+            fail!(
+                "`{} {}` failed with {}: {}",
+                &self.name,
+                args.join(" "),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+impl ContainerRuntime for CliRuntime {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn program(&self) -> &PathBuf {
+        &self.program
+    }
+
+    fn command(&self, args: &[String]) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(args);
+        cmd
+    }
+
+    fn pull(&self, image: &str) -> DmgrResult {
+        self.exec(&[String::from("pull"), image.to_string()])?;
+        Ok(())
+    }
+
+    fn run(&self, args: &[String]) -> DmgrResult<ContainerId> {
+        let out = self.exec(args)?;
+        last_line(&out).ok_or(dmgr_err!("{} did not report a container id", &self.name))
+    }
+
+    fn stop(&self, id: &str) -> DmgrResult {
+        self.exec(&[String::from("stop"), id.to_string()])?;
+        Ok(())
+    }
+
+    fn is_running(&self, id: &str) -> DmgrResult<bool> {
+        let out = self.exec(&[
+            String::from("inspect"),
+            String::from("--format"),
+            String::from("{{.State.Running}}"),
+            id.to_string(),
+        ])?;
+        Ok(out.trim() == "true")
+    }
+
+    fn pid(&self, id: &str) -> DmgrResult<Pid> {
+        let out = self.exec(&[
+            String::from("inspect"),
+            String::from("--format"),
+            String::from("{{.State.Pid}}"),
+            id.to_string(),
+        ])?;
+        Ok(Pid::from_str(out.trim())?)
+    }
+}
+
+// Selects the runtime named by $DMGR_CONTAINER_RUNTIME, defaulting to docker
+pub fn runtime() -> Box<dyn ContainerRuntime> {
+    let spec = env::var(constants::CONTAINER_RUNTIME_ENV_VAR)
+        .unwrap_or(String::from(constants::DEFAULT_CONTAINER_RUNTIME));
+    runtime_from(&spec)
+}
+
+// Accepts "docker", "podman" or a path to a docker-compatible executable
+pub fn runtime_from(spec: &str) -> Box<dyn ContainerRuntime> {
+    match spec {
+        "docker" => Box::new(CliRuntime::docker()),
+        "podman" => Box::new(CliRuntime::podman()),
+        program => Box::new(CliRuntime::from_program(PathBuf::from(program))),
+    }
+}
+
+// The runtime a container in a runfile was started with
+pub fn runtime_for(runfile: &Runfile) -> Box<dyn ContainerRuntime> {
+    runtime_from(
+        runfile
+            .container_runtime
+            .as_ref()
+            .map(|r| r.as_str())
+            .unwrap_or(constants::DEFAULT_CONTAINER_RUNTIME),
+    )
+}
+
+// Whether the runfile's container is still running, as its runtime reports
+// it. A container the runtime no longer knows of is not.
+pub fn is_running(runfile: &Runfile) -> bool {
+    match runfile.container_id {
+        Some(ref id) => runtime_for(runfile).is_running(id).unwrap_or(false),
+        None => false,
+    }
+}
+
+pub fn container_name(svc: &Service) -> String {
+    format!("dmgr-{}", svc.name)
+}
+
+// Builds `run` arguments from the service's image and ports
+pub fn run_args(svc: &Service, detached: bool) -> Vec<String> {
+    let mut args = vec![
+        String::from("run"),
+        String::from("--rm"),
+        String::from("--name"),
+        container_name(svc),
+    ];
+
+    if detached {
+        args.push(String::from("-d"));
+    }
+
+    for port in &svc.ports {
+        args.push(String::from("-p"));
+        args.push(format!("{}:{}", port, port));
+    }
+
+    args.push(svc.image());
+    args
+}
+
+pub fn last_line(out: &str) -> Option<String> {
+    out.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .last()
+        .map(|l| l.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scratch::ScratchDir;
+
+    // Speaks just enough of the docker CLI for one container, abc123
+    const FAKE_RUNTIME: &str = r#"#!/bin/sh
+case "$1" in
+    run) echo "Unable to find image locally"; echo; echo abc123 ;;
+    stop) [ "$2" = abc123 ] || { echo "no such container: $2" >&2; exit 1; } ;;
+    inspect)
+        [ "$4" = abc123 ] || { echo "no such object: $4" >&2; exit 1; }
+        case "$3" in *Running*) echo true ;; *) echo 4242 ;; esac ;;
+    *) exit 2 ;;
+esac
+"#;
+
+    fn fake_runtime(test: &str) -> (ScratchDir, CliRuntime) {
+        let dir = ScratchDir::new(&format!("container-{}", test));
+        let program = dir.write_executable("fake-docker", FAKE_RUNTIME);
+        (dir, CliRuntime::from_program(program))
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn names_the_runtime_after_the_program() {
+        let (_dir, runtime) = fake_runtime("name");
+        assert_eq!(runtime.name(), "fake-docker");
+        assert_eq!(runtime_from("podman").name(), "podman");
+    }
+
+    #[test]
+    fn run_reports_the_last_line_as_the_id() {
+        let (_dir, runtime) = fake_runtime("run");
+        assert_eq!(runtime.run(&args(&["run", "-d", "img"])).unwrap(), "abc123");
+    }
+
+    #[test]
+    fn stop_fails_with_the_runtime_error() {
+        let (_dir, runtime) = fake_runtime("stop");
+        assert!(runtime.stop("abc123").is_ok());
+
+        let e = runtime.stop("nope").unwrap_err().to_string();
+        assert!(e.contains("no such container: nope"), "{}", e);
+    }
+
+    #[test]
+    fn pid_is_parsed_from_inspect() {
+        let (_dir, runtime) = fake_runtime("pid");
+        assert_eq!(runtime.pid("abc123").unwrap(), 4242);
+        assert!(runtime.pid("nope").is_err());
+    }
+
+    #[test]
+    fn containers_are_running_while_the_runtime_says_so() {
+        let (_dir, runtime) = fake_runtime("running");
+        assert!(runtime.is_running("abc123").unwrap());
+
+        let runfile = |id: &str| Runfile {
+            pid: None,
+            started: None,
+            is_container: true,
+            mode: Default::default(),
+            command: None,
+            capture: Default::default(),
+            log_offset: 0,
+            container_id: Some(id.to_string()),
+            container_runtime: runtime.program().to_str().map(String::from),
+        };
+        assert!(is_running(&runfile("abc123")));
+        assert!(!is_running(&runfile("gone")));
+    }
+
+    #[test]
+    fn unknown_commands_fail() {
+        let (_dir, runtime) = fake_runtime("unknown");
+        assert!(runtime.pull("img").is_err());
+    }
+
+    #[test]
+    fn missing_program_fails() {
+        let runtime = CliRuntime::from_program(PathBuf::from("/nonexistent/docker"));
+        let e = runtime.stop("abc123").unwrap_err().to_string();
+        assert!(e.contains("could not run"), "{}", e);
+    }
+}
@@ -0,0 +1,176 @@
+// Resolves service dependencies declared with `depends_on`
+use log::warn;
+
+use std::collections::btree_set::BTreeSet;
+
+use command::DmgrResult;
+use config::ServiceRegistry;
+use service::Service;
+
+// Returns the named services plus everything they transitively depend on,
+// ordered so that each service comes after all of its dependencies
+pub fn start_order(registry: &ServiceRegistry, names: &[String]) -> DmgrResult<Vec<Service>> {
+    let mut resolver = Resolver {
+        registry,
+        done: BTreeSet::new(),
+        order: vec![],
+    };
+
+    for name in names {
+        resolver.visit(name, &mut vec![])?;
+    }
+
+    Ok(resolver.order)
+}
+
+// Orders just the named services by their dependencies, falling back to the
+// given order when those can't be resolved
+pub fn order_among(registry: &ServiceRegistry, names: &[String]) -> Vec<Service> {
+    match start_order(registry, names) {
+        Ok(order) => order
+            .into_iter()
+            .filter(|s| names.contains(&s.name))
+            .collect(),
+        Err(e) => {
+            warn!("{}; using registry order", e);
+            names
+                .iter()
+                .filter_map(|n| registry.get_service(n).ok())
+                .collect()
+        }
+    }
+}
+
+struct Resolver<'r> {
+    registry: &'r ServiceRegistry,
+    done: BTreeSet<String>,
+    order: Vec<Service>,
+}
+
+impl<'r> Resolver<'r> {
+    // Depth-first walk; `path` holds the chain of services currently being
+    // visited, so meeting one of them again means there is a cycle
+    fn visit(&mut self, name: &str, path: &mut Vec<String>) -> DmgrResult {
+        if self.done.contains(name) {
+            return Ok(());
+        }
+
+        if let Some(i) = path.iter().position(|p| p == name) {
+            let mut cycle = path[i..].to_vec();
+            cycle.push(name.to_string());
+            fail!("dependency cycle: {}", cycle.join(" -> "))
+        }
+
+        if let Some(parent) = path.last() {
+            if !self.registry.content.contains_key(name) {
+                fail!(
+                    "{:?} depends on {:?}, which is not registered",
+                    parent,
+                    name
+                )
+            }
+        }
+
+        let svc = self.registry.get_service(name)?;
+
+        path.push(name.to_string());
+        for dep in &svc.depends_on {
+            self.visit(dep, path)?;
+        }
+        path.pop();
+
+        self.done.insert(name.to_string());
+        self.order.push(svc);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use constants;
+    use scratch::ScratchDir;
+    use settings::Settings;
+    use std::collections::btree_map::BTreeMap;
+    use std::path::Path;
+
+    // A registry of services, each in its own repo in the scratch dir, with
+    // the given dependencies
+    fn registry(test: &str, services: &[(&str, &[&str])]) -> (ScratchDir, ServiceRegistry) {
+        Settings::init_for_tests();
+        let root = ScratchDir::new(&format!("deps-{}", test));
+
+        let mut registry = ServiceRegistry {
+            path: root.join("service-registry.json"),
+            content: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            schema_version: constants::REGISTRY_SCHEMA_VERSION,
+        };
+        for &(name, deps) in services {
+            let cfg = Path::new(name)
+                .join(constants::SERVICE_CONFIG_DIR)
+                .join(format!("{}.json", name));
+            let cfg = root.write(cfg, &format!(r#"{{ "depends_on": {:?} }}"#, deps));
+            registry = registry.add_cfg(&cfg).unwrap();
+        }
+        (root, registry)
+    }
+
+    fn names(services: Vec<Service>) -> Vec<String> {
+        services.into_iter().map(|s| s.name).collect()
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn starts_a_diamond_from_the_bottom_once() {
+        let (_root, registry) = registry(
+            "diamond",
+            &[
+                ("app", &["api", "worker"]),
+                ("api", &["db"]),
+                ("worker", &["db"]),
+                ("db", &[]),
+            ],
+        );
+
+        let order = start_order(&registry, &strings(&["app"])).unwrap();
+        assert_eq!(names(order), strings(&["db", "api", "worker", "app"]));
+    }
+
+    #[test]
+    fn orders_only_the_named_services() {
+        let (_root, registry) = registry("among", &[("api", &["db"]), ("db", &[]), ("web", &[])]);
+
+        let order = order_among(&registry, &strings(&["web", "api"]));
+        assert_eq!(names(order), strings(&["web", "api"]));
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let (_root, registry) = registry("cycle", &[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+
+        let e = start_order(&registry, &strings(&["a"])).unwrap_err();
+        assert_eq!(e.to_string(), "dependency cycle: a -> b -> c -> a");
+    }
+
+    #[test]
+    fn reports_missing_dependencies() {
+        let (_root, registry) = registry("missing", &[("api", &["db"])]);
+
+        let e = start_order(&registry, &strings(&["api"])).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "\"api\" depends on \"db\", which is not registered"
+        );
+    }
+
+    #[test]
+    fn unknown_services_are_errors() {
+        let (_root, registry) = registry("unknown", &[]);
+        assert!(start_order(&registry, &strings(&["nope"])).is_err());
+        assert!(order_among(&registry, &strings(&["nope"])).is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+// Runs the health checks declared in a service's `health_checks`
+extern crate regex;
+extern crate serde_derive;
+
+use self::regex::Regex;
+use self::serde_derive::Serialize;
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use command::DmgrResult;
+use config::HealthCheck;
+use service;
+use service::Service;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+pub struct ServiceReport {
+    pub name: String,
+    pub healthy: bool,
+    pub checks: Vec<CheckReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    pub check: String,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub latency_ms: u64,
+}
+
+// Runs each check that goes into `Service::is_ready`, one by one, and times it
+pub fn report(svc: &Service) -> ServiceReport {
+    let mut checks = vec![timed("running".to_string(), || {
+        if svc.is_running() {
+            Ok(())
+        } else {
+            err!("no live process and no open ports")
+        }
+    })];
+
+    if !svc.ports.is_empty() {
+        checks.push(timed(format!("ports {:?}", &svc.ports), || {
+            if svc.open_ports().is_empty() {
+                err!("none are open")
+            } else {
+                Ok(())
+            }
+        }));
+    }
+
+    if let Some(ref check) = svc.http_check {
+        checks.push(timed(format!("http_check {}", &check.path), || {
+            check.run(&svc.probe_hosts(), &svc.ports).map(|_| ())
+        }));
+    }
+
+    for check in &svc.health_checks {
+        checks.push(timed(check.describe(), || check.run(svc)));
+    }
+
+    ServiceReport {
+        name: svc.name.clone(),
+        healthy: checks.iter().all(|c| c.passed),
+        checks,
+    }
+}
+
+fn timed<F: FnOnce() -> DmgrResult>(check: String, f: F) -> CheckReport {
+    let started = Instant::now();
+    let result = f();
+
+    CheckReport {
+        check,
+        passed: result.is_ok(),
+        error: result.err().map(|e| e.to_string()),
+        latency_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+impl HealthCheck {
+    // Ok when the check passes, otherwise an error saying why it didn't
+    pub fn run(&self, svc: &Service) -> DmgrResult {
+        match *self {
+            HealthCheck::Tcp { port, ref host } => {
+                let hosts = match *host {
+                    Some(ref host) => vec![host.clone()],
+                    None => svc.probe_hosts(),
+                };
+                if service::tcp_is_available(&hosts, port) {
+                    Ok(())
+                } else {
+                    err!("nothing is listening on port {} at {:?}", port, hosts)
+                }
+            }
+            HealthCheck::Http(ref check) => check.run(&svc.probe_hosts(), &svc.ports).map(|_| ()),
+            HealthCheck::Command {
+                ref command,
+                exit_code,
+            } => check_command(svc, command, exit_code.unwrap_or(0)),
+            HealthCheck::File { ref path } => {
+                let path = svc.repo_path.join(path);
+                if path.exists() {
+                    Ok(())
+                } else {
+                    err!("{:?} does not exist", path)
+                }
+            }
+            HealthCheck::LogLine { ref pattern } => check_log_line(svc, pattern),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            HealthCheck::Tcp { port, .. } => format!("tcp {}", port),
+            HealthCheck::Http(ref check) => match check.port {
+                Some(port) => format!("http :{}{}", port, &check.path),
+                None => format!("http {}", &check.path),
+            },
+            HealthCheck::Command { ref command, .. } => format!("command {:?}", command),
+            HealthCheck::File { ref path } => format!("file {:?}", path),
+            HealthCheck::LogLine { ref pattern } => format!("log line /{}/", pattern),
+        }
+    }
+}
+
+// Runs through the shell in the service's repo, so pipes and the like work
+fn check_command(svc: &Service, command: &str, exit_code: i32) -> DmgrResult {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(&svc.repo_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return match status.code() {
+                Some(code) if code == exit_code => Ok(()),
+                _ => err!("{:?} exited with {}", command, status),
+            };
+        }
+
+        if started.elapsed() >= CHECK_TIMEOUT {
+            child.kill()?;
+            child.wait()?;
+            fail!("{:?} timed out", command)
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// Only looks at output written since the service was last spawned, so a line
+// from an earlier run doesn't count
+fn check_log_line(svc: &Service, pattern: &str) -> DmgrResult {
+    let re = regex(pattern)?;
+    let offset = svc.runfile().map(|r| r.log_offset).unwrap_or(0);
+
+    let mut file = File::open(svc.log_file()?)?;
+    let len = file.metadata()?.len();
+    // a smaller log means it was rotated or truncated since, so read it all
+    file.seek(SeekFrom::Start(if offset <= len { offset } else { 0 }))?;
+
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+
+    if String::from_utf8_lossy(&bytes)
+        .lines()
+        .any(|l| re.is_match(l))
+    {
+        Ok(())
+    } else {
+        err!("no log line matching /{}/ yet", pattern)
+    }
+}
+
+fn regex(pattern: &str) -> DmgrResult<Regex> {
+    Regex::new(pattern).map_err(|e| dmgr_err!("invalid pattern /{}/: {}", pattern, e))
+}
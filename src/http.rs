@@ -0,0 +1,553 @@
+// A minimal HTTP/1.1 client for probing services
+extern crate regex;
+
+use self::regex::Regex;
+
+use std::cmp;
+use std::collections::btree_map::BTreeMap;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::IpAddr;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
+
+use command::DmgrResult;
+use config::HttpCheck;
+use config::StatusPattern;
+use constants;
+
+// Bodies are only read to match them, so there is no need to take in more
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    // Header names are case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref k, _)| k.eq_ignore_ascii_case(name))
+            .map(|&(_, ref v)| v.as_str())
+    }
+}
+
+impl HttpCheck {
+    // Ok with the final response when it has an accepted status and a
+    // matching body. Each host is tried with each port (just its own port, if
+    // it has one) and the first one that answers is used.
+    pub fn run(&self, hosts: &[String], ports: &[u16]) -> DmgrResult<Response> {
+        let ports = match self.port {
+            Some(port) => vec![port],
+            None => ports.to_vec(),
+        };
+        if ports.is_empty() {
+            fail!("http check on {:?} needs a port", &self.path)
+        }
+
+        let timeout = Duration::from_millis(
+            self.timeout_ms
+                .unwrap_or(constants::DEFAULT_HTTP_TIMEOUT_MILLIS),
+        );
+        let max_redirects = self
+            .max_redirects
+            .unwrap_or(constants::DEFAULT_HTTP_MAX_REDIRECTS);
+
+        let mut last_err = dmgr_err!("no host to check");
+        for host in hosts {
+            for &port in &ports {
+                match get_following(
+                    host,
+                    port,
+                    &self.path,
+                    &self.headers,
+                    timeout,
+                    max_redirects,
+                ) {
+                    Ok(response) => return self.verify(response),
+                    Err(e) => last_err = e,
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    // Catches what serde can't when the service config is loaded
+    pub fn validate(&self) -> DmgrResult {
+        for pattern in &self.accept {
+            if let StatusPattern::Class(ref class) = *pattern {
+                if pattern.class().is_none() {
+                    fail!(
+                        "bad status {:?} in http_check accept; use e.g. 200 or \"2xx\"",
+                        class
+                    )
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn verify(&self, response: Response) -> DmgrResult<Response> {
+        let accepted = if self.accept.is_empty() {
+            response.status < 400
+        } else {
+            self.accept.iter().any(|p| p.matches(response.status))
+        };
+        if !accepted {
+            fail!("GET {} returned {}", &self.path, response.status)
+        }
+
+        if let Some(ref pattern) = self.body {
+            let re = Regex::new(pattern)
+                .map_err(|e| dmgr_err!("invalid pattern /{}/: {}", pattern, e))?;
+            if !re.is_match(&response.body) {
+                fail!("GET {} body did not match /{}/", &self.path, pattern)
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+impl StatusPattern {
+    // A class like "2xx" matches every status starting with that digit
+    pub fn matches(&self, status: u16) -> bool {
+        match *self {
+            StatusPattern::Code(code) => code == status,
+            StatusPattern::Class(_) => self.class() == Some(status / 100),
+        }
+    }
+
+    // The leading digit of a class, if it is one of 1xx to 5xx
+    fn class(&self) -> Option<u16> {
+        match *self {
+            StatusPattern::Class(ref class) => match class.as_bytes() {
+                &[digit, x1, x2]
+                    if (b'1'..=b'5').contains(&digit)
+                        && x1.eq_ignore_ascii_case(&b'x')
+                        && x2.eq_ignore_ascii_case(&b'x') =>
+                {
+                    Some(u16::from(digit - b'0'))
+                }
+                _ => None,
+            },
+            StatusPattern::Code(_) => None,
+        }
+    }
+}
+
+// Follows redirects on the probed host only, as the check is about this
+// service and not wherever it sends clients. With `max_redirects` at 0 a
+// redirect is returned like any other response.
+pub fn get_following(
+    host: &str,
+    port: u16,
+    path: &str,
+    headers: &BTreeMap<String, String>,
+    timeout: Duration,
+    max_redirects: usize,
+) -> DmgrResult<Response> {
+    let (mut port, mut path) = (port, path.to_string());
+    let mut redirects = 0;
+
+    loop {
+        let response = get(host, port, &path, headers, timeout)?;
+        let location = match response.header("Location") {
+            Some(l) if response.status >= 300 && response.status < 400 => l.to_string(),
+            _ => return Ok(response),
+        };
+        if max_redirects == 0 {
+            return Ok(response);
+        }
+        if redirects == max_redirects {
+            fail!("too many redirects from {} (max {})", &path, max_redirects)
+        }
+        redirects += 1;
+
+        let (h, p, pa) = redirect_target(&location, host, port, &path)?;
+        if !same_host(&h, host) {
+            fail!("{} redirects to {:?}, off {}", &path, location, host)
+        }
+        port = p;
+        path = pa;
+    }
+}
+
+// One GET request. `timeout` applies to connecting and, separately, to the
+// whole of reading the response.
+pub fn get(
+    host: &str,
+    port: u16,
+    path: &str,
+    headers: &BTreeMap<String, String>,
+    timeout: Duration,
+) -> DmgrResult<Response> {
+    let mut stream = connect(host, port, timeout)?;
+
+    let mut request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}:{}\r\nConnection: close\r\nUser-Agent: dmgr\r\n",
+        path,
+        bracketed(host),
+        port
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    let mut reader = BufReader::new(DeadlineReader {
+        stream,
+        deadline: Instant::now() + timeout,
+    });
+    read_response(&mut reader)
+}
+
+fn connect(host: &str, port: u16, timeout: Duration) -> DmgrResult<TcpStream> {
+    let mut last_err = dmgr_err!("could not resolve {}:{}", host, port);
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = e.into(),
+        }
+    }
+    Err(last_err)
+}
+
+fn read_response<R: BufRead>(reader: &mut R) -> DmgrResult<Response> {
+    let status_line = read_line(reader)?;
+    let status = status_code(&status_line)?;
+
+    let mut headers = vec![];
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(i) = line.find(':') {
+            headers.push((
+                line[..i].trim().to_string(),
+                line[i + 1..].trim().to_string(),
+            ));
+        }
+    }
+
+    let mut response = Response {
+        status,
+        headers,
+        body: String::new(),
+    };
+
+    let chunked = response
+        .header("Transfer-Encoding")
+        .map_or(false, |te| te.to_lowercase().contains("chunked"));
+    let length = response
+        .header("Content-Length")
+        .and_then(|l| u64::from_str(l).ok());
+
+    let body = if chunked {
+        read_chunked(reader)?
+    } else {
+        let mut body = vec![];
+        let limit = cmp::min(length.unwrap_or(MAX_BODY_BYTES), MAX_BODY_BYTES);
+        reader.take(limit).read_to_end(&mut body)?;
+        body
+    };
+    response.body = String::from_utf8_lossy(&body).to_string();
+
+    Ok(response)
+}
+
+fn read_chunked<R: BufRead>(reader: &mut R) -> DmgrResult<Vec<u8>> {
+    let mut body = vec![];
+
+    loop {
+        let line = read_line(reader)?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size =
+            u64::from_str_radix(size, 16).map_err(|_| dmgr_err!("bad chunk size {:?}", line))?;
+        if size == 0 || body.len() as u64 + size > MAX_BODY_BYTES {
+            return Ok(body);
+        }
+
+        reader.take(size).read_to_end(&mut body)?;
+        read_line(reader)?;
+    }
+}
+
+// A line without its CRLF; fails if the connection closes first
+fn read_line<R: BufRead>(reader: &mut R) -> DmgrResult<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        fail!("connection closed before the response was complete")
+    }
+    Ok(line
+        .trim_end_matches(|c| c == '\r' || c == '\n')
+        .to_string())
+}
+
+// e.g. "HTTP/1.1 200 OK"
+fn status_code(line: &str) -> DmgrResult<u16> {
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some(version), Some(code)) if version.starts_with("HTTP/") => {
+            u16::from_str(code).map_err(|_| dmgr_err!("bad HTTP status line {:?}", line))
+        }
+        _ => err!("bad HTTP status line {:?}", line),
+    }
+}
+
+// Resolves a Location header against the request it answered. Only plain
+// HTTP can be followed.
+fn redirect_target(
+    location: &str,
+    host: &str,
+    port: u16,
+    path: &str,
+) -> DmgrResult<(String, u16, String)> {
+    if location.starts_with("https://") {
+        fail!(
+            "cannot follow redirect to {:?}; only http is supported",
+            location
+        )
+    }
+
+    if location.starts_with("http://") {
+        let rest = &location["http://".len()..];
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+        // IPv6 literals are bracketed, e.g. [::1]:8080
+        let port_sep = match authority.rfind(']') {
+            Some(end) => authority[end..].find(':').map(|i| end + i),
+            None => authority.rfind(':'),
+        };
+        let (host, port) = match port_sep {
+            Some(i) => {
+                let port = u16::from_str(&authority[i + 1..])
+                    .map_err(|_| dmgr_err!("bad redirect location {:?}", location))?;
+                (&authority[..i], port)
+            }
+            None => (authority, 80),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        return Ok((host.to_string(), port, path));
+    }
+
+    if location.starts_with('/') {
+        return Ok((host.to_string(), port, location.to_string()));
+    }
+
+    // relative to the directory of the current path
+    let dir = match path.rfind('/') {
+        Some(i) => &path[..i + 1],
+        None => "/",
+    };
+    Ok((host.to_string(), port, format!("{}{}", dir, location)))
+}
+
+// Loopback names all count as the same host, as a service may redirect to
+// localhost while it's probed on 127.0.0.1
+fn same_host(a: &str, b: &str) -> bool {
+    let loopback = |h: &str| {
+        h.eq_ignore_ascii_case("localhost")
+            || IpAddr::from_str(h).map_or(false, |ip| ip.is_loopback())
+    };
+    a.eq_ignore_ascii_case(b) || (loopback(a) && loopback(b))
+}
+
+fn bracketed(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
+}
+
+// Reads with a single deadline for the whole response, so a server that
+// trickles bytes can't hold a probe open forever
+struct DeadlineReader {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Err(timed_out());
+        }
+        self.stream.set_read_timeout(Some(self.deadline - now))?;
+        self.stream.read(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => timed_out(),
+            _ => e,
+        })
+    }
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        "timed out waiting for the response",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn parse(raw: &str) -> DmgrResult<Response> {
+        read_response(&mut Cursor::new(raw.as_bytes().to_vec()))
+    }
+
+    // Answers each of the next `n` connections on a local port with `reply`
+    fn serve(n: usize, reply: String) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(n) {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(reply.as_bytes());
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn reads_headers_and_a_sized_body() {
+        let response =
+            parse("HTTP/1.1 200 OK\r\ncontent-length: 5\r\nX-Up:  yes \r\n\r\nhello, world")
+                .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("X-UP"), Some("yes"));
+        assert_eq!(response.body, "hello");
+    }
+
+    #[test]
+    fn reads_a_chunked_body() {
+        let response = parse(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(response.body, "hello, world");
+    }
+
+    #[test]
+    fn rejects_bad_responses() {
+        assert!(parse("SSH-2.0-OpenSSH\r\n\r\n").is_err());
+        assert!(parse("HTTP/1.1 abc OK\r\n\r\n").is_err());
+        assert!(parse("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n").is_err());
+        assert!(parse("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n").is_err());
+    }
+
+    #[test]
+    fn resolves_redirect_targets() {
+        let target = |l| redirect_target(l, "127.0.0.1", 8080, "/a/b").unwrap();
+        assert_eq!(
+            target("/c"),
+            ("127.0.0.1".to_string(), 8080, "/c".to_string())
+        );
+        assert_eq!(
+            target("c"),
+            ("127.0.0.1".to_string(), 8080, "/a/c".to_string())
+        );
+        assert_eq!(
+            target("http://localhost:9000/d"),
+            ("localhost".to_string(), 9000, "/d".to_string())
+        );
+        assert_eq!(
+            target("http://[::1]:9000"),
+            ("::1".to_string(), 9000, "/".to_string())
+        );
+        assert_eq!(target("http://example.com/e").1, 80);
+        assert!(redirect_target("https://example.com/", "127.0.0.1", 8080, "/").is_err());
+        assert!(redirect_target("http://example.com:x/", "127.0.0.1", 8080, "/").is_err());
+    }
+
+    #[test]
+    fn matches_status_patterns() {
+        assert!(StatusPattern::Code(204).matches(204));
+        assert!(StatusPattern::Class("2XX".to_string()).matches(204));
+        assert!(!StatusPattern::Class("2xx".to_string()).matches(301));
+        for bad in &["20x", "6xx", "2x", "é"] {
+            assert!(!StatusPattern::Class(bad.to_string()).matches(200));
+            assert_eq!(StatusPattern::Class(bad.to_string()).class(), None);
+        }
+    }
+
+    #[test]
+    fn follows_redirects_on_the_same_host() {
+        let port = serve(
+            1,
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_string(),
+        );
+        let start = serve(
+            1,
+            format!(
+                "HTTP/1.1 302 Found\r\nLocation: http://localhost:{}/up\r\n\r\n",
+                port
+            ),
+        );
+
+        let response = get_following(
+            "127.0.0.1",
+            start,
+            "/",
+            &BTreeMap::new(),
+            Duration::from_secs(2),
+            5,
+        )
+        .unwrap();
+        assert_eq!(response.body, "ok");
+    }
+
+    #[test]
+    fn fails_on_too_many_redirects() {
+        let port = serve(
+            3,
+            "HTTP/1.1 301 Moved\r\nLocation: /again\r\n\r\n".to_string(),
+        );
+        let e = get_following(
+            "127.0.0.1",
+            port,
+            "/",
+            &BTreeMap::new(),
+            Duration::from_secs(2),
+            2,
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("too many redirects"), "{}", e);
+    }
+
+    #[test]
+    fn stays_on_the_probed_host() {
+        let port = serve(
+            1,
+            "HTTP/1.1 302 Found\r\nLocation: http://example.com/\r\n\r\n".to_string(),
+        );
+        let e = get_following(
+            "127.0.0.1",
+            port,
+            "/",
+            &BTreeMap::new(),
+            Duration::from_secs(2),
+            5,
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("off 127.0.0.1"), "{}", e);
+    }
+}
@@ -0,0 +1,142 @@
+// Reads service log files
+extern crate chrono;
+
+use self::chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+use std::cmp;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use command::DmgrResult;
+
+const CHUNK_SIZE: u64 = 8192;
+
+const TIMESTAMP_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"];
+
+// The last `n` lines of a file. Reads backwards from the end, so huge logs
+// don't have to be read in full.
+pub fn tail(path: &PathBuf, n: usize) -> DmgrResult<Vec<String>> {
+    let mut file = File::open(path)?;
+    let mut pos = file.metadata()?.len();
+    let mut buf: Vec<u8> = vec![];
+
+    // n complete lines need n + 1 newlines, unless the start of the file is reached
+    while pos > 0 && buf.iter().filter(|&&b| b == b'\n').count() <= n {
+        let chunk = cmp::min(CHUNK_SIZE, pos);
+        pos -= chunk;
+
+        let mut bytes = vec![0; chunk as usize];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut bytes)?;
+        bytes.extend(buf);
+        buf = bytes;
+    }
+
+    let lines: Vec<String> = String::from_utf8_lossy(&buf)
+        .lines()
+        .map(String::from)
+        .collect();
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines.into_iter().skip(skip).collect())
+}
+
+pub fn lines(path: &PathBuf) -> DmgrResult<Vec<String>> {
+    let file = File::open(path)?;
+    let lines = BufReader::new(file)
+        .split(b'\n')
+        .filter_map(Result::ok)
+        .map(|l| {
+            String::from_utf8_lossy(&l)
+                .trim_end_matches('\r')
+                .to_string()
+        })
+        .collect();
+    Ok(lines)
+}
+
+// Yields lines appended to a file since the last poll
+pub struct Follower {
+    path: PathBuf,
+    pos: u64,
+    partial: String,
+}
+
+impl Follower {
+    // Starts from the current end of the file
+    pub fn new(path: PathBuf) -> Self {
+        let pos = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        Follower {
+            path,
+            pos,
+            partial: String::new(),
+        }
+    }
+
+    pub fn poll(&mut self) -> DmgrResult<Vec<String>> {
+        let mut file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(_) => return Ok(vec![]),
+        };
+        let len = file.metadata()?.len();
+
+        // the file was truncated or rotated, so start again from the top
+        if len < self.pos {
+            self.pos = 0;
+            self.partial.clear();
+        }
+
+        if len == self.pos {
+            return Ok(vec![]);
+        }
+
+        let mut bytes = vec![];
+        file.seek(SeekFrom::Start(self.pos))?;
+        file.take(len - self.pos).read_to_end(&mut bytes)?;
+        self.pos += bytes.len() as u64;
+        self.partial.push_str(&String::from_utf8_lossy(&bytes));
+
+        // the last piece is an incomplete line, kept until its newline arrives
+        let mut lines: Vec<String> = self
+            .partial
+            .split('\n')
+            .map(|l| l.trim_end_matches('\r').to_string())
+            .collect();
+        self.partial = lines.pop().unwrap_or_default();
+
+        Ok(lines)
+    }
+}
+
+// Parses a timestamp at the start of a log line, either RFC 3339 or local
+// time as "YYYY-MM-DD HH:MM:SS[.fff]", optionally in square brackets
+pub fn timestamp(line: &str) -> Option<DateTime<Local>> {
+    let line = line.trim_start().trim_start_matches('[');
+    let mut words = line.split_whitespace();
+    let first = words.next()?.trim_end_matches(']');
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(first) {
+        return Some(dt.with_timezone(&Local));
+    }
+
+    let candidates = match words.next() {
+        Some(second) => vec![
+            first.to_string(),
+            format!("{} {}", first, second.trim_end_matches(']')),
+        ],
+        None => vec![first.to_string()],
+    };
+
+    for candidate in candidates {
+        // log4j and friends separate milliseconds with a comma
+        let candidate = candidate.replace(',', ".");
+        for fmt in TIMESTAMP_FORMATS.iter() {
+            if let Ok(naive) = NaiveDateTime::parse_from_str(&candidate, fmt) {
+                return Local.from_local_datetime(&naive).single();
+            }
+        }
+    }
+
+    None
+}
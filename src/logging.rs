@@ -1,20 +1,30 @@
-extern crate home;
 use log::LevelFilter;
+use log::Record;
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::console::Target;
 use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use log4rs::filter::threshold::ThresholdFilter;
+use log4rs::filter::{Filter, Response};
 
-use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use settings::Settings;
 
-const LOG_LVL: LevelFilter = log::LevelFilter::Trace;
 const LOG_PAT: &'static str = "{h({d(%Y-%m-%d %H:%M:%S%.3f)} - {l} - {m})}\n";
 
-pub fn init() {
-    let (dmgr_name, dmgr_appender) = dmgr_logger();
-    let (console_name, console_appender) = console_logger();
+// Cleared while something else owns the terminal, e.g. `top -i`, so log
+// lines don't land on top of it. They still go to dmgr's log file.
+static CONSOLE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_console(enabled: bool) {
+    CONSOLE_ENABLED.store(enabled, Ordering::Relaxed)
+}
+
+pub fn init(settings: &Settings) {
+    let (dmgr_name, dmgr_appender) = dmgr_logger(settings);
+    let (console_name, console_appender) = console_logger(settings.log_level);
 
     let config = Config::builder()
         .appender(console_appender)
@@ -23,7 +33,7 @@ pub fn init() {
             Root::builder()
                 .appender(console_name)
                 .appender(dmgr_name)
-                .build(LOG_LVL),
+                .build(settings.log_level),
         )
         .unwrap();
 
@@ -31,35 +41,45 @@ pub fn init() {
 }
 
 // logs all dmgr activity
-fn dmgr_logger() -> (&'static str, Appender) {
+fn dmgr_logger(settings: &Settings) -> (&'static str, Appender) {
     let name = "dmgr";
-    let log_path: Result<PathBuf, &'static str> = match home::home_dir() {
-        Some(path) => Ok(path.join(format!("{}.test.log", &name))),
-        None => Err("problem determining home dir"),
-    };
-
     let logfile = FileAppender::builder()
         .encoder(Box::new(PatternEncoder::new(LOG_PAT)))
-        .build(log_path.unwrap())
+        .build(settings.dmgr_log())
         .unwrap();
 
     (name, Appender::builder().build(name, Box::new(logfile)))
 }
 
-// logs to console
-fn console_logger() -> (&'static str, Appender) {
+// logs to the console, on stderr so it stays out of output meant for other
+// programs, e.g. health --json
+fn console_logger(level: LevelFilter) -> (&'static str, Appender) {
     let name = "console";
-    let stdout = ConsoleAppender::builder()
+    let stderr = ConsoleAppender::builder()
         .encoder(Box::new(PatternEncoder::new(LOG_PAT)))
-        .target(Target::Stdout)
+        .target(Target::Stderr)
         .build();
 
     let appender = Appender::builder()
-        .filter(Box::new(ThresholdFilter::new(LOG_LVL)))
-        .build(name, Box::new(stdout));
+        .filter(Box::new(ConsoleSwitch))
+        .filter(Box::new(ThresholdFilter::new(level)))
+        .build(name, Box::new(stderr));
     (name, appender)
 }
 
+#[derive(Debug)]
+struct ConsoleSwitch;
+
+impl Filter for ConsoleSwitch {
+    fn filter(&self, _: &Record) -> Response {
+        if CONSOLE_ENABLED.load(Ordering::Relaxed) {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
 #[allow(dead_code)]
 // might need a special logger for formatting
 fn table_logger() {}
@@ -10,26 +10,48 @@ extern crate sysinfo;
 mod macros;
 
 mod args;
+mod capture;
+mod color;
 mod command;
 mod config;
 mod constants;
+mod container;
+mod deps;
+mod health;
+mod http;
+mod logfile;
 mod logging;
+mod migrations;
+mod process;
+mod rotation;
 mod runner;
+#[cfg(test)]
+mod scratch;
 mod service;
+mod settings;
+mod status;
+mod table;
+mod terminal;
+mod watcher;
 
 use clap::ArgMatches;
 use log::error;
 
+use command::config::ConfigRunner;
+use command::group::GroupRunner;
+use command::health::HealthRunner;
 use command::list::ListRunner;
+use command::logs::LogsRunner;
 use command::register::RegisterRunner;
+use command::restart::RestartRunner;
 use command::start::StartRunner;
 use command::stop::StopRunner;
+use command::supervise::SuperviseRunner;
+use command::top::TopRunner;
 use command::{DmgrErr, DmgrResult, Runnable, Subcommand};
-use std::process;
+use settings::Settings;
 
 fn main() -> DmgrResult {
-    logging::init();
-
     // TODO: will print back trace
     if true {
         std::env::set_var("RUST_BACKTRACE", "1");
@@ -41,8 +63,16 @@ fn main() -> DmgrResult {
 
     let app = args::new();
     let matches = app.get_matches();
-
-    //    println!("Matches = {:#?}", matches);
+    // logging isn't set up until the settings are, so report on stderr
+    let settings = match Settings::init(&matches) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1)
+        }
+    };
+    logging::init(&settings);
+    color::set_mode(settings.color);
 
     //    let p = PathBuf::from("/Users/tkbrigham/developer/socrata/core/.solo/core.json");
     //    let s = Service::from_path(&p).unwrap();
@@ -54,14 +84,23 @@ fn main() -> DmgrResult {
             (ListRunner::NAME, Some(args)) => ListRunner { args }.run(),
             (StartRunner::NAME, Some(args)) => StartRunner { args }.run(),
             (StopRunner::NAME, Some(args)) => StopRunner { args }.run(),
+            (RestartRunner::NAME, Some(args)) => RestartRunner { args }.run(),
             (RegisterRunner::NAME, Some(args)) => RegisterRunner { args }.run(),
+            (GroupRunner::NAME, Some(args)) => GroupRunner { args }.run(),
+            (LogsRunner::NAME, Some(args)) => LogsRunner { args }.run(),
+            (HealthRunner::NAME, Some(args)) => HealthRunner { args }.run(),
+            (TopRunner::NAME, Some(args)) => TopRunner { args }.run(),
+            (ConfigRunner::NAME, Some(args)) => ConfigRunner { args }.run(),
+            (SuperviseRunner::NAME, Some(args)) => SuperviseRunner { args }.run(),
             _ => Err(DmgrErr::new("unknown")),
         }
     }
 
     if let Err(e) = run(matches) {
-        error!("{}\n{:?}", e, e.stacktrace);
-        process::exit(1)
+        if !e.silent {
+            error!("{}\n{:?}", e, e.stacktrace);
+        }
+        std::process::exit(1)
     }
 
     Ok(())
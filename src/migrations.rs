@@ -0,0 +1,105 @@
+// Brings registries written by older versions of dmgr up to the current schema
+extern crate serde_json;
+
+use self::serde_json::{Map, Value};
+
+use std::path::PathBuf;
+
+use command::DmgrResult;
+use constants;
+
+type Migration = fn(Value, &PathBuf) -> DmgrResult<Value>;
+
+// MIGRATIONS[n] takes a registry from schema version n + 1 to n + 2
+const MIGRATIONS: [Migration; 1] = [wrap_services];
+
+// Registries from before the envelope are a bare map of services, version 1
+pub fn schema_version(registry: &Value) -> DmgrResult<u32> {
+    match *registry {
+        Value::Object(ref fields) => match fields.get("schema_version") {
+            Some(version) => match version.as_u64() {
+                Some(v) if v >= 1 && v <= u64::from(u32::max_value()) => Ok(v as u32),
+                _ => err!("bad schema_version {}; versions start at 1", version),
+            },
+            None => Ok(1),
+        },
+        _ => err!("the registry is not a JSON object"),
+    }
+}
+
+// Applies each migration from the registry's version on, in order. The
+// result is only written back when the registry is next saved.
+pub fn migrate(mut registry: Value, path: &PathBuf) -> DmgrResult<Value> {
+    let mut version = schema_version(&registry)?;
+    if version > constants::REGISTRY_SCHEMA_VERSION {
+        fail!(
+            "{:?} has schema version {}, but this dmgr only knows up to {}; upgrade dmgr",
+            path,
+            version,
+            constants::REGISTRY_SCHEMA_VERSION
+        )
+    }
+
+    while version < constants::REGISTRY_SCHEMA_VERSION {
+        registry = MIGRATIONS[version as usize - 1](registry, path)?;
+        version += 1;
+        registry["schema_version"] = Value::from(version);
+    }
+
+    Ok(registry)
+}
+
+// 1 -> 2: the map of services moves into an envelope, next to the groups
+fn wrap_services(registry: Value, _: &PathBuf) -> DmgrResult<Value> {
+    let mut envelope = Map::new();
+    envelope.insert("services".to_string(), registry);
+    envelope.insert("groups".to_string(), Value::Object(Map::new()));
+    Ok(Value::Object(envelope))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::serde_json::json;
+    use super::*;
+
+    fn path() -> PathBuf {
+        PathBuf::from("/tmp/service-registry.json")
+    }
+
+    #[test]
+    fn bare_maps_are_version_1() {
+        assert_eq!(schema_version(&json!({ "api": {} })).unwrap(), 1);
+        assert_eq!(schema_version(&json!({ "schema_version": 2 })).unwrap(), 2);
+    }
+
+    #[test]
+    fn wraps_services_in_an_envelope() {
+        let services = json!({ "api": { "repo_path": "/src/api" } });
+        assert_eq!(
+            migrate(services.clone(), &path()).unwrap(),
+            json!({ "schema_version": 2, "services": services, "groups": {} })
+        );
+    }
+
+    #[test]
+    fn leaves_current_registries_alone() {
+        let registry = json!({ "schema_version": 2, "services": {}, "groups": { "g": ["api"] } });
+        assert_eq!(migrate(registry.clone(), &path()).unwrap(), registry);
+    }
+
+    #[test]
+    fn rejects_newer_registries() {
+        let e = migrate(json!({ "schema_version": 99 }), &path())
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("upgrade dmgr"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_bad_versions() {
+        for version in &[json!(0), json!(-1), json!(1u64 << 40), json!("2")] {
+            let registry = json!({ "schema_version": version });
+            assert!(migrate(registry, &path()).is_err(), "{}", version);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+// Inspects and signals OS processes
+extern crate libc;
+
+use sysinfo::ProcessExt;
+use sysinfo::ProcessStatus;
+use sysinfo::SystemExt;
+
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use config::Pid;
+
+// Runs the command in a new session, so it leads its own process group and is
+// detached from dmgr's terminal
+pub fn detach(cmd: &mut Command) {
+    cmd.stdin(Stdio::null());
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+// Ignores SIGTERM and SIGINT from here on. Children spawned before this keep
+// the default handlers, as ignored signals are inherited across exec.
+pub fn ignore_termination() {
+    unsafe {
+        libc::signal(libc::SIGTERM, libc::SIG_IGN);
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+    }
+}
+
+pub fn is_alive(pid: Pid) -> bool {
+    is_alive_in(&sysinfo::System::new(), pid)
+}
+
+// Zombies have already exited and are only waiting to be reaped
+pub fn is_alive_in(system: &sysinfo::System, pid: Pid) -> bool {
+    match system.get_process(pid) {
+        Some(p) => match p.status() {
+            ProcessStatus::Zombie => false,
+            _ => true,
+        },
+        None => false,
+    }
+}
+
+// When the process started, to tell it apart from a later process given the
+// same pid. Only comparable with other start times from the same machine.
+// None once the process has exited.
+#[cfg(target_os = "linux")]
+pub fn start_time(pid: Pid) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the command name, in parens, may contain spaces, so count the fields
+    // from after it; the start time, in clock ticks since boot, is the 22nd
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    fields.get(19)?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn start_time(pid: Pid) -> Option<u64> {
+    sysinfo::System::new()
+        .get_process(pid)
+        .map(|p| p.start_time())
+}
+
+// Whether `pid` is still the process that started at `started`, rather than
+// another one given its pid since. Once the process has exited the pid can't
+// be reused while its group is alive, so a group outliving its leader is
+// still the service's. Runfiles from before start times were recorded can't
+// tell, so are trusted.
+pub fn is_same_process(pid: Pid, started: Option<u64>) -> bool {
+    match (started, start_time(pid)) {
+        (Some(then), Some(now)) => then == now,
+        _ => true,
+    }
+}
+
+// A group can outlive its leader, e.g. when a wrapper script exits and
+// leaves its children running. Pids below 1 name dmgr's own group, or every
+// process, to kill(), so they never count as a service's tree.
+pub fn tree_is_alive(pid: Pid) -> bool {
+    pid > 0 && (group_exists(pid) || is_alive(pid))
+}
+
+pub fn tree_is_alive_in(system: &sysinfo::System, pid: Pid) -> bool {
+    pid > 0 && (group_exists(pid) || is_alive_in(system, pid))
+}
+
+pub fn terminate_tree(pid: Pid) -> bool {
+    signal_tree(pid, libc::SIGTERM)
+}
+
+pub fn kill_tree(pid: Pid) -> bool {
+    signal_tree(pid, libc::SIGKILL)
+}
+
+// Signals the process group led by `pid`. Processes started before dmgr ran
+// services in their own group don't lead one, so for those each process in
+// the tree is signalled instead.
+fn signal_tree(pid: Pid, sig: libc::c_int) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+    if unsafe { libc::kill(-pid, sig) } == 0 {
+        return true;
+    }
+
+    let mut tree = descendants(pid);
+    tree.push(pid);
+    tree.into_iter()
+        .filter(|p| unsafe { libc::kill(*p, sig) } == 0)
+        .count()
+        > 0
+}
+
+fn group_exists(pgid: Pid) -> bool {
+    unsafe { libc::kill(-pgid, 0) == 0 }
+}
+
+// All processes below `pid`, parents before their children
+pub fn descendants(pid: Pid) -> Vec<Pid> {
+    descendants_in(&sysinfo::System::new(), pid)
+}
+
+pub fn descendants_in(system: &sysinfo::System, pid: Pid) -> Vec<Pid> {
+    let processes = system.get_process_list();
+
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i];
+        for (child, p) in processes.iter() {
+            if p.parent() == Some(parent) && !tree.contains(child) {
+                tree.push(*child);
+            }
+        }
+        i += 1;
+    }
+
+    tree.remove(0);
+    tree
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub cpu_percent: f32,
+    pub memory_kb: u64,
+}
+
+// Summed over the process and everything it spawned. CPU use is only known
+// if the system's processes were refreshed at least twice.
+pub fn tree_usage(system: &sysinfo::System, pid: Pid) -> Usage {
+    if pid <= 0 {
+        return Usage::default();
+    }
+
+    let mut tree = descendants_in(system, pid);
+    tree.push(pid);
+
+    tree.iter()
+        .filter_map(|p| system.get_process(*p))
+        .fold(Usage::default(), |usage, p| Usage {
+            cpu_percent: usage.cpu_percent + p.cpu_usage(),
+            memory_kb: usage.memory_kb + p.memory(),
+        })
+}
+
+// Returns whether the process and everything it spawned exited before the
+// timeout
+pub fn wait_for_exit(pid: Pid, timeout: Duration) -> bool {
+    let started = Instant::now();
+
+    while tree_is_alive(pid) {
+        if started.elapsed() >= timeout {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn start_times_tell_processes_apart() {
+        let pid = process::id() as Pid;
+        let started = start_time(pid);
+        assert!(started.is_some());
+        assert_eq!(start_time(pid), started);
+
+        assert!(is_same_process(pid, started));
+        assert!(!is_same_process(pid, started.map(|t| t + 1)));
+        assert!(is_same_process(pid, None));
+    }
+
+    #[test]
+    fn exited_processes_have_no_start_time() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id() as Pid;
+        child.wait().unwrap();
+
+        assert_eq!(start_time(pid), None);
+    }
+}
@@ -0,0 +1,231 @@
+// Size-based rotation of service log files
+extern crate flate2;
+
+use self::flate2::write::GzEncoder;
+use self::flate2::Compression;
+use log::info;
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use command::DmgrResult;
+use config::ByteSize;
+use config::LogRotationContent;
+use settings::Settings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogRotation {
+    // rotation is disabled when this is 0
+    pub max_size: u64,
+    pub keep: usize,
+    pub compress: bool,
+}
+
+impl LogRotation {
+    // Each setting comes from the service config if given there, else from
+    // dmgr's settings
+    pub fn resolve(cfg: &LogRotationContent) -> DmgrResult<Self> {
+        let defaults = Settings::get()?.log_rotation;
+        let max_size = match cfg.max_size {
+            Some(ref size) => size.bytes()?,
+            None => defaults.max_size,
+        };
+
+        Ok(LogRotation {
+            max_size,
+            keep: cfg.keep.unwrap_or(defaults.keep),
+            compress: cfg.compress.unwrap_or(defaults.compress),
+        })
+    }
+
+    // Shifts `<log>.1` to `<log>.2` and so on, dropping the oldest, then moves
+    // the log itself to `<log>.1`. Only meant to run while nothing is writing
+    // to the log, e.g. just before a service is spawned.
+    pub fn rotate_if_needed(&self, log: &PathBuf) -> DmgrResult<bool> {
+        let size = match fs::metadata(log) {
+            Ok(m) => m.len(),
+            Err(_) => return Ok(false),
+        };
+
+        if self.max_size == 0 || size < self.max_size {
+            return Ok(false);
+        }
+
+        info!("rotating {:?} ({} bytes, max {})", log, size, self.max_size);
+
+        if self.keep == 0 {
+            fs::remove_file(log)?;
+            return Ok(true);
+        }
+
+        remove_if_exists(&rotated(log, self.keep, false))?;
+        remove_if_exists(&rotated(log, self.keep, true))?;
+
+        for i in (1..self.keep).rev() {
+            for &gz in [false, true].iter() {
+                let from = rotated(log, i, gz);
+                if from.exists() {
+                    fs::rename(&from, rotated(log, i + 1, gz))?;
+                }
+            }
+        }
+
+        let newest = rotated(log, 1, false);
+        fs::rename(log, &newest)?;
+
+        if self.compress {
+            gzip(&newest, &rotated(log, 1, true))?;
+            fs::remove_file(&newest)?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl ByteSize {
+    pub fn bytes(&self) -> DmgrResult<u64> {
+        match *self {
+            ByteSize::Bytes(n) => Ok(n),
+            ByteSize::Human(ref s) => parse_size(s),
+        }
+    }
+}
+
+fn rotated(log: &PathBuf, i: usize, gz: bool) -> PathBuf {
+    let ext = if gz { ".gz" } else { "" };
+    PathBuf::from(format!("{}.{}{}", log.display(), i, ext))
+}
+
+fn remove_if_exists(path: &PathBuf) -> DmgrResult {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn gzip(src: &PathBuf, dest: &PathBuf) -> DmgrResult {
+    let mut encoder = GzEncoder::new(File::create(dest)?, Compression::default());
+    io::copy(&mut File::open(src)?, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+// Accepts plain bytes or a K, M or G suffix, e.g. "512", "10K", "100MB"
+pub fn parse_size(s: &str) -> DmgrResult<u64> {
+    let trimmed = s.trim().trim_end_matches(|c| c == 'B' || c == 'b');
+    let (digits, multiplier) = match trimmed.chars().last() {
+        Some('K') | Some('k') => (&trimmed[..trimmed.len() - 1], 1024),
+        Some('M') | Some('m') => (&trimmed[..trimmed.len() - 1], 1024 * 1024),
+        Some('G') | Some('g') => (&trimmed[..trimmed.len() - 1], 1024 * 1024 * 1024),
+        _ => (trimmed, 1),
+    };
+
+    let n = u64::from_str(digits.trim())
+        .map_err(|_| dmgr_err!("could not parse size {:?}; use e.g. 512, 10K or 100M", s))?;
+    n.checked_mul(multiplier)
+        .ok_or(dmgr_err!("size {:?} is too large", s))
+}
+
+pub fn parse_bool(s: &str) -> DmgrResult<bool> {
+    match s.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => fail!("could not parse {:?} as true or false", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scratch::ScratchDir;
+
+    fn read(path: &PathBuf) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10K").unwrap(), 10 * 1024);
+        assert_eq!(parse_size(" 100mb ").unwrap(), 100 * 1024 * 1024);
+        assert_eq!(parse_size("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn rejects_bad_or_overflowing_sizes() {
+        for s in &["", "K", "ten", "-1", "1.5M", "20000000000G"] {
+            assert!(parse_size(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn parses_bools() {
+        assert!(parse_bool("Yes").unwrap());
+        assert!(!parse_bool(" off ").unwrap());
+        assert!(parse_bool("maybe").is_err());
+    }
+
+    #[test]
+    fn leaves_small_logs_alone() {
+        let dir = ScratchDir::new("rotation-small");
+        let log = dir.write("svc.log", "abc");
+        let rotation = LogRotation {
+            max_size: 10,
+            keep: 2,
+            compress: false,
+        };
+        assert!(!rotation.rotate_if_needed(&log).unwrap());
+        assert_eq!(read(&log), "abc");
+    }
+
+    #[test]
+    fn shifts_old_logs_and_drops_the_oldest() {
+        let dir = ScratchDir::new("rotation-shift");
+        let log = dir.write("svc.log", "newest");
+        fs::write(rotated(&log, 1, false), "older").unwrap();
+        fs::write(rotated(&log, 2, false), "oldest").unwrap();
+        let rotation = LogRotation {
+            max_size: 1,
+            keep: 2,
+            compress: false,
+        };
+
+        assert!(rotation.rotate_if_needed(&log).unwrap());
+        assert!(!log.exists());
+        assert_eq!(read(&rotated(&log, 1, false)), "newest");
+        assert_eq!(read(&rotated(&log, 2, false)), "older");
+        assert!(!rotated(&log, 3, false).exists());
+    }
+
+    #[test]
+    fn compresses_rotated_logs() {
+        let dir = ScratchDir::new("rotation-gzip");
+        let log = dir.write("svc.log", "newest");
+        let rotation = LogRotation {
+            max_size: 1,
+            keep: 1,
+            compress: true,
+        };
+
+        assert!(rotation.rotate_if_needed(&log).unwrap());
+        assert!(!rotated(&log, 1, false).exists());
+        assert!(rotated(&log, 1, true).exists());
+    }
+
+    #[test]
+    fn keeping_none_removes_the_log() {
+        let dir = ScratchDir::new("rotation-none");
+        let log = dir.write("svc.log", "newest");
+        let rotation = LogRotation {
+            max_size: 1,
+            keep: 0,
+            compress: false,
+        };
+
+        assert!(rotation.rotate_if_needed(&log).unwrap());
+        assert!(!log.exists());
+    }
+}
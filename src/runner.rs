@@ -1,14 +1,14 @@
 // Runs commands
-extern crate home;
-
 use std::fs::OpenOptions;
 use std::path::PathBuf;
 use std::process::Command;
 
+use settings::Settings;
+
 pub fn run() {
-    let log_path: Result<PathBuf, &str> = match home::home_dir() {
-        Some(path) => Ok(path.join("runner.out.log")),
-        None => Err("problem determining home dir"),
+    let log_path: Result<PathBuf, String> = match Settings::get() {
+        Ok(settings) => Ok(settings.state_dir.join("runner.out.log")),
+        Err(e) => Err(format!("problem resolving dmgr's settings: {}", e)),
     };
 
     let file_out = OpenOptions::new()
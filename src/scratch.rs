@@ -0,0 +1,63 @@
+// Scratch dirs for tests, removed again when dropped
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    // Named for the test, and the test process, so no two tests share one
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("dmgr-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        ScratchDir { path }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path.join(path)
+    }
+
+    // Writes a file, and any dirs it is in
+    pub fn write<P: AsRef<Path>>(&self, path: P, contents: &str) -> PathBuf {
+        let path = self.path.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    // Writes a script through sh rather than from here: another test forking
+    // while this process held the file open for writing would keep it open,
+    // and running it would fail with ETXTBSY
+    pub fn write_executable<P: AsRef<Path>>(&self, path: P, contents: &str) -> PathBuf {
+        let path = self.path.join(path);
+        let mut sh = Command::new("sh")
+            .arg("-c")
+            .arg("cat > \"$0\" && chmod +x \"$0\"")
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        sh.stdin
+            .take()
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+        assert!(sh.wait().unwrap().success());
+        path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -1,23 +1,31 @@
-extern crate home;
 extern crate serde_json;
 
+use log::warn;
 use sysinfo::SystemExt;
 
-use command::DmgrErr;
 use command::DmgrResult;
+use config::CaptureMode;
+use config::HealthCheck;
+use config::HealthCheckContent;
+use config::HttpCheck;
+use config::LogRotationContent;
 use config::Runfile;
 use config::ServiceConfigContent;
+use config::WatchConfigContent;
+use constants;
+use container;
+use process;
+use settings::Settings;
+use status;
+use status::Snapshot;
 use std::ffi::OsStr;
+use std::fs::create_dir_all;
 use std::fs::File;
-use std::fs::OpenOptions;
-use std::io::BufRead;
-use std::io::BufReader;
 use std::io::Write;
-use std::net::Shutdown;
-use std::net::SocketAddr;
 use std::net::TcpStream;
+use std::net::ToSocketAddrs;
 use std::path::PathBuf;
-use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Service {
@@ -27,15 +35,24 @@ pub struct Service {
     pub start_process: Option<ServiceCommand>,
     pub start_dev_mode: Option<ServiceCommand>,
     pub start_container: Option<ServiceCommand>,
-    pub http_check: Option<String>,
-    pub health_checks: Vec<String>,
+    pub depends_on: Vec<String>,
+    pub watch: WatchConfigContent,
+    pub http_check: Option<HttpCheck>,
+    pub health_checks: Vec<HealthCheck>,
     pub image_name: Option<String>, // consider making getter that falls back to name
     pub image_tag: Option<String>,
     //    log_file: PathBuf, TODO make function
     pub aliases: Vec<String>,
+    pub tags: Vec<String>,
     pub ports: Vec<u16>,
+    pub host: Option<String>,
+    pub log_rotation: LogRotationContent,
+    pub log_capture: CaptureMode,
+    pub stop_grace_period: Duration,
+    pub kill_timeout: Duration,
     pub requires_sudo: bool,
     pub register_by_default: bool,
+    pub hidden: bool,
 }
 
 impl Service {
@@ -53,45 +70,83 @@ impl Service {
             start_process: config_content.start_process,
             start_dev_mode: config_content.start_dev_mode,
             start_container: config_content.start_container,
-            http_check: config_content.http_check,
-            health_checks: config_content.health_checks.unwrap_or(vec![]),
+            depends_on: config_content.depends_on.unwrap_or(vec![]),
+            watch: config_content.watch.unwrap_or_default(),
+            http_check: config_content.http_check.map(HttpCheck::from),
+            health_checks: config_content
+                .health_checks
+                .unwrap_or(vec![])
+                .into_iter()
+                .map(|check| {
+                    if let HealthCheckContent::Command(ref command) = check {
+                        warn!(
+                            "{:?}: running untyped health check {:?} as a shell command",
+                            path, command
+                        );
+                    }
+                    HealthCheck::from(check)
+                })
+                .collect(),
             image_name: config_content.image_name,
-            image_tag: None,
+            image_tag: config_content.image_tag,
             aliases: config_content.aliases.unwrap_or(vec![]),
+            tags: config_content.tags.unwrap_or(vec![]),
             ports: config_content.ports.unwrap_or(vec![]),
+            host: config_content.host,
+            log_rotation: config_content.log_rotation.unwrap_or_default(),
+            log_capture: config_content.log_capture.unwrap_or_default(),
+            stop_grace_period: config_content
+                .stop_grace_period
+                .map(Duration::from_secs)
+                .unwrap_or(Settings::get()?.stop_grace_period),
+            kill_timeout: Duration::from_secs(
+                config_content
+                    .kill_timeout
+                    .unwrap_or(constants::DEFAULT_KILL_TIMEOUT_SECS),
+            ),
             requires_sudo: config_content.requires_sudo.unwrap_or(false),
             register_by_default: config_content.register_by_default.unwrap_or(true),
+            hidden: config_content.hidden.unwrap_or(false),
         };
 
+        if let Some(ref check) = svc.http_check {
+            check.validate()?;
+        }
+
         Ok(svc)
     }
 
     pub fn log_file(&self) -> DmgrResult<PathBuf> {
-        let home = home::home_dir().ok_or(dmgr_err!("could not determine home dir"))?;
-        Ok(home
-            .join(".solo")
-            .join("log")
-            .join(format!("{}.log", self.name)))
+        Ok(Settings::get()?.log_dir.join(format!("{}.log", self.name)))
     }
 
     pub fn run_file(&self) -> DmgrResult<PathBuf> {
-        let home = home::home_dir().ok_or(dmgr_err!("could not determine home dir"))?;
-        Ok(home
-            .join(".solo")
-            .join("run")
+        Ok(Settings::get()?
+            .run_dir()
             .join(format!("{}.json", self.name)))
     }
 
     pub fn pid(&self) -> DmgrResult<i32> {
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .read(true)
-            .open(self.run_file()?)?;
+        self.runfile()?
+            .pid
+            .ok_or(dmgr_err!("no pid recorded for {:?}", &self.name))
+    }
 
-        let runfile: Runfile = serde_json::from_reader(file)?;
+    pub fn runfile(&self) -> DmgrResult<Runfile> {
+        let path = self.run_file()?;
+        let file = File::open(&path).map_err(|_| dmgr_err!("no runfile found at {:?}", path))?;
+        Ok(serde_json::from_reader(file)?)
+    }
 
-        Ok(runfile.pid)
+    // Image reference to run, falling back to the service name and "latest"
+    pub fn image(&self) -> String {
+        let name = self.image_name.as_ref().unwrap_or(&self.name);
+        let tag = self
+            .image_tag
+            .as_ref()
+            .map(|t| t.as_str())
+            .unwrap_or(constants::DEFAULT_IMAGE_TAG);
+        format!("{}:{}", name, tag)
     }
 
     pub fn from_name(s: &str) -> DmgrResult<Self> {
@@ -102,94 +157,75 @@ impl Service {
         Self::from_path(&PathBuf::from(s))
     }
 
-    pub fn row(self) -> Vec<String> {
-        vec![
-            self.name.clone(),
-            self.status(),
-            format!("{:?}", self.ports),
-        ]
-    }
-
-    fn status(&self) -> String {
-        let status = if self.is_ready() {
-            "running"
-        } else if self.is_waiting() {
-            "waiting"
-        } else {
-            "-"
-        };
-
-        if self.is_disowned() {
-            format!("{}*", status)
-        } else {
-            format!("{}", status)
-        }
-    }
-
     // Could be disowned, could be owned by dmgr
     pub fn is_running(&self) -> bool {
-        self.has_active_pid() || self.has_ports_defined_and_open()
-    }
-
-    pub fn is_disowned(&self) -> bool {
-        !self.has_active_pid() && self.has_ports_defined_and_open()
+        self.has_active_pid() || !self.open_ports().is_empty()
     }
 
     pub fn is_ready(&self) -> bool {
-        self.is_running() && !self.is_waiting()
+        self.snapshot().is_ready()
     }
 
-    pub fn is_waiting(&self) -> bool {
-        self.is_running()
-            && (self.has_http_check_defined_and_failing()
-                || self.has_ports_defined_and_all_closed())
+    pub fn snapshot(&self) -> Snapshot {
+        status::probe(self, &sysinfo::System::new())
     }
 
-    pub fn has_http_check_defined_and_failing(&self) -> bool {
-        self.http_check.is_some() && !self.http_check_passing()
+    pub fn has_health_check_failing(&self) -> bool {
+        self.health_checks.iter().any(|c| c.run(self).is_err())
     }
 
-    pub fn has_ports_defined_and_all_closed(&self) -> bool {
-        self.ports.len() > 0 && self.open_ports().len() == 0
-    }
-
-    pub fn has_ports_defined_and_open(&self) -> bool {
-        self.ports.len() > 0 && self.open_ports().len() > 0
-    }
-
-    pub fn open_ports(&self) -> Vec<&u16> {
+    pub fn open_ports(&self) -> Vec<u16> {
+        let hosts = self.probe_hosts();
         self.ports
             .iter()
-            .filter(|&port| tcp_is_available("0.0.0.0", *port))
+            .cloned()
+            .filter(|&port| tcp_is_available(&hosts, port))
             .collect()
     }
 
     pub fn http_check_passing(&self) -> bool {
-        let endpoint = match self.http_check {
-            Some(ref e) => e,
-            None => return true,
-        };
-
-        let addrs: Vec<SocketAddr> = self
-            .ports
-            .clone()
-            .into_iter()
-            .map(|p| SocketAddr::from(([0, 0, 0, 0], p)))
-            .collect();
+        match self.http_check {
+            Some(ref check) => check.run(&self.probe_hosts(), &self.ports).is_ok(),
+            None => true,
+        }
+    }
 
-        get_success(addrs, endpoint).is_ok()
+    // Where to look for the service's listeners: its configured host, or
+    // else both loopback addresses, since a service may bind either one
+    pub fn probe_hosts(&self) -> Vec<String> {
+        match self.host {
+            Some(ref host) => vec![host.clone()],
+            None => constants::DEFAULT_PROBE_HOSTS
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+        }
     }
 
     pub fn has_active_pid(&self) -> bool {
-        let system = sysinfo::System::new();
-        let p = match self.pid() {
-            Ok(pid) => pid,
-            Err(_) => return false,
+        self.live_runfile_in(&sysinfo::System::new()).is_some()
+    }
+
+    // The runfile, while what it recorded is still running. A container is
+    // asked after through its runtime, as its pid may be one in the
+    // runtime's VM. The pid of a process is often a wrapper script, so it
+    // counts as running while anything in its process group is, as long as
+    // the pid hasn't been given to another process since.
+    pub fn live_runfile_in(&self, system: &sysinfo::System) -> Option<Runfile> {
+        let runfile = self.runfile().ok()?;
+        let alive = if runfile.is_container {
+            container::is_running(&runfile)
+        } else {
+            runfile.pid.map_or(false, |pid| {
+                process::is_same_process(pid, runfile.started)
+                    && process::tree_is_alive_in(system, pid)
+            })
         };
 
-        match system.get_process(p) {
-            Some(_) => true,
-            None => false,
+        if alive {
+            Some(runfile)
+        } else {
+            None
         }
     }
 
@@ -201,19 +237,33 @@ impl Service {
             start_process: None,
             start_dev_mode: None,
             start_container: None,
+            depends_on: vec![],
+            watch: WatchConfigContent::default(),
             http_check: None,
             health_checks: vec![],
             image_name: None,
             image_tag: None,
             aliases: vec![],
+            tags: vec![],
             ports: vec![],
+            host: None,
+            log_rotation: LogRotationContent::default(),
+            log_capture: CaptureMode::default(),
+            stop_grace_period: Duration::from_secs(constants::DEFAULT_STOP_GRACE_PERIOD_SECS),
+            kill_timeout: Duration::from_secs(constants::DEFAULT_KILL_TIMEOUT_SECS),
             requires_sudo: false,
             register_by_default: true,
+            hidden: false,
         }
     }
 
     pub fn update_runfile(&self, r: Runfile) -> DmgrResult {
-        let mut file = File::create(self.run_file()?)?;
+        let path = self.run_file()?;
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+
+        let mut file = File::create(path)?;
         let mut content = serde_json::to_string_pretty(&r)?;
         content.push_str("\n");
 
@@ -222,37 +272,6 @@ impl Service {
     }
 }
 
-fn http_status_code(line: &str) -> DmgrResult<u16> {
-    let mut parts = line.split_whitespace();
-    parts.next();
-    let code = parts
-        .next()
-        .ok_or(dmgr_err!("could not determine HTTP status code"))?;
-    u16::from_str(code).map_err(|e| DmgrErr::new(&e.to_string()))
-}
-
-fn status_code_ok(code: u16) -> bool {
-    code < 400
-}
-
-fn get_success(addrs: Vec<SocketAddr>, endpoint: &String) -> DmgrResult {
-    let mut stream = TcpStream::connect(&addrs[..])?;
-    stream.write(format!("GET {} HTTP/1.1\r\n", endpoint).as_bytes())?;
-    stream.shutdown(Shutdown::Write)?;
-
-    let mut buf = String::new();
-    let mut buffered = BufReader::new(stream);
-    buffered.read_line(&mut buf)?;
-
-    let code = http_status_code(buf.trim())?;
-
-    if !status_code_ok(code) {
-        fail!("received error code from server: {:?}", code)
-    }
-
-    Ok(())
-}
-
 pub type ServiceCommand = String;
 
 // TODO: this is implemented in register.rs too
@@ -265,6 +284,12 @@ fn repo_path_for(canonical_path: &PathBuf) -> PathBuf {
     PathBuf::from(repo.unwrap())
 }
 
-fn tcp_is_available(host: &str, port: u16) -> bool {
-    TcpStream::connect((host, port)).is_ok()
+// Whether anything accepts connections on the port at any of the hosts
+pub fn tcp_is_available(hosts: &[String], port: u16) -> bool {
+    let timeout = Duration::from_millis(constants::PORT_PROBE_TIMEOUT_MILLIS);
+    hosts
+        .iter()
+        .filter_map(|host| (host.as_str(), port).to_socket_addrs().ok())
+        .flat_map(|addrs| addrs)
+        .any(|addr| TcpStream::connect_timeout(&addr, timeout).is_ok())
 }
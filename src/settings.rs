@@ -0,0 +1,422 @@
+// Where dmgr keeps its files, and user-wide defaults from dmgr.toml
+extern crate home;
+extern crate lazy_static;
+extern crate serde_derive;
+extern crate toml;
+
+use self::lazy_static::lazy_static;
+use self::serde_derive::Deserialize;
+use clap::ArgMatches;
+use log::LevelFilter;
+
+use std::collections::btree_map::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::str::FromStr;
+#[cfg(test)]
+use std::sync::Once;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+#[cfg(test)]
+use args;
+use color::ColorMode;
+use command::DmgrResult;
+use config::LogRotationContent;
+use constants;
+use rotation;
+use rotation::LogRotation;
+#[cfg(test)]
+use scratch::ScratchDir;
+
+// Each setting is resolved, highest first, from:
+// - the global flags, e.g. --home or --log_level
+// - its DMGR_* env var
+// - dmgr.toml in the config dir
+// - the defaults. The dirs default to ~/.solo if it exists, which keeps
+//   everything in one dir like DMGR_HOME does, else the XDG base dirs:
+//   config for the registry and dmgr.toml, state for runfiles and logs.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub registry: PathBuf,
+    pub config_dir: PathBuf,
+    pub state_dir: PathBuf,
+    pub log_dir: PathBuf,
+    pub start_timeout: Duration,
+    // for services that don't set their own
+    pub stop_grace_period: Duration,
+    pub log_rotation: LogRotation,
+    pub color: ColorMode,
+    pub list_columns: Vec<String>,
+    pub log_level: LevelFilter,
+    // where each setting came from, by its key in dmgr.toml
+    pub sources: BTreeMap<&'static str, Source>,
+    // the global flags given, as (flag, value)
+    pub flags: Vec<(&'static str, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(&'static str),
+    Flag(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Default => write!(f, "default"),
+            Source::File(ref path) => write!(f, "{}", path.display()),
+            Source::Env(var) => write!(f, "${}", var),
+            Source::Flag(flag) => write!(f, "--{}", flag),
+        }
+    }
+}
+
+// Global flags, with the env var each one overrides and whether it takes a
+// path
+const FLAGS: [(&str, &str, bool); 5] = [
+    ("home", constants::HOME_ENV_VAR, true),
+    ("registry", constants::REGISTRY_ENV_VAR, true),
+    ("log_dir", constants::LOG_DIR_ENV_VAR, true),
+    ("color", constants::COLOR_ENV_VAR, false),
+    ("log_level", constants::LOG_LEVEL_ENV_VAR, false),
+];
+
+lazy_static! {
+    // Resolved once in main, as every service load, runfile and probe reads them
+    static ref SETTINGS: RwLock<Option<Arc<Settings>>> = RwLock::new(None);
+}
+
+impl Settings {
+    pub fn init(matches: &ArgMatches) -> DmgrResult<Arc<Self>> {
+        // global flags can be given at any level of subcommand
+        let mut levels = vec![matches];
+        while let Some(sub) = levels[levels.len() - 1].subcommand().1 {
+            levels.push(sub);
+        }
+
+        let mut flags = vec![];
+        for &(flag, _, is_path) in FLAGS.iter() {
+            if let Some(value) = levels.iter().rev().filter_map(|m| m.value_of(flag)).next() {
+                let value = if is_path {
+                    absolute(value)?.to_string_lossy().to_string()
+                } else {
+                    value.to_string()
+                };
+                flags.push((flag, value));
+            }
+        }
+
+        let settings = Arc::new(Self::resolve(flags)?);
+        *SETTINGS.write().unwrap() = Some(settings.clone());
+        Ok(settings)
+    }
+
+    // Everything from the defaults, in a scratch home dir. Resolving only
+    // works out the paths, so the dir can go again straight away.
+    #[cfg(test)]
+    pub fn init_for_tests() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let home = ScratchDir::new("home");
+            let home = home.path().to_string_lossy().to_string();
+            let matches = args::new().get_matches_from(vec!["dmgr", "--home", &home, "list"]);
+            Settings::init(&matches).unwrap();
+        });
+    }
+
+    pub fn get() -> DmgrResult<Arc<Self>> {
+        match *SETTINGS.read().unwrap() {
+            Some(ref settings) => Ok(settings.clone()),
+            None => fail!("dmgr's settings are used before they are resolved"),
+        }
+    }
+
+    // The global flags again, for the dmgr processes dmgr runs itself, so
+    // they resolve the same settings
+    pub fn args(&self) -> Vec<String> {
+        self.flags
+            .iter()
+            .flat_map(|&(flag, ref value)| vec![format!("--{}", flag), value.clone()])
+            .collect()
+    }
+
+    fn resolve(flags: Vec<(&'static str, String)>) -> DmgrResult<Self> {
+        let (dir, dir_source) = match given(&flags, constants::HOME_ENV_VAR) {
+            Some((home, source)) => (Some(PathBuf::from(home)), source),
+            None => {
+                let home = home::home_dir().ok_or(dmgr_err!("could not determine home dir"))?;
+                let legacy = home.join(constants::SERVICE_CONFIG_DIR);
+                (Some(legacy).filter(|d| d.is_dir()), Source::Default)
+            }
+        };
+        let (config_dir, state_dir) = match dir {
+            Some(dir) => (dir.clone(), dir),
+            None => xdg_dirs()?,
+        };
+
+        let path = config_dir.join(constants::SETTINGS_FILENAME);
+        let file = SettingsFile::read(&path)?;
+        let rotation = file.log_rotation.unwrap_or_default();
+        let mut r = Resolver {
+            path,
+            flags,
+            sources: BTreeMap::new(),
+        };
+
+        // paths from dmgr.toml or the env vars are relative to the config dir
+        let parse_path = |s: &str| Ok(config_dir.join(s));
+        let registry = r.pick(
+            "registry",
+            constants::REGISTRY_ENV_VAR,
+            None,
+            config_dir.join(constants::SERVICE_REGISTRY_FILENAME),
+            parse_path,
+        )?;
+        let log_dir = r.pick(
+            "log_dir",
+            constants::LOG_DIR_ENV_VAR,
+            file.log_dir.map(|dir| config_dir.join(dir)),
+            state_dir.join("log"),
+            parse_path,
+        )?;
+        // the dirs, and anything still defaulting to a path in them, come
+        // from DMGR_HOME when it is set
+        for key in &["config_dir", "state_dir", "registry", "log_dir"] {
+            if r.sources.get(key).map_or(true, |s| *s == Source::Default) {
+                r.sources.insert(*key, dir_source.clone());
+            }
+        }
+
+        Ok(Settings {
+            registry,
+            log_dir,
+            start_timeout: r.pick(
+                "start_timeout",
+                constants::START_TIMEOUT_ENV_VAR,
+                file.start_timeout.map(Duration::from_secs),
+                Duration::from_secs(constants::DEFAULT_START_TIMEOUT_SECS),
+                parse_secs,
+            )?,
+            stop_grace_period: r.pick(
+                "stop_grace_period",
+                constants::STOP_GRACE_PERIOD_ENV_VAR,
+                file.stop_grace_period.map(Duration::from_secs),
+                Duration::from_secs(constants::DEFAULT_STOP_GRACE_PERIOD_SECS),
+                parse_secs,
+            )?,
+            log_rotation: LogRotation {
+                max_size: r.pick(
+                    "log_rotation.max_size",
+                    constants::LOG_MAX_SIZE_ENV_VAR,
+                    match rotation.max_size {
+                        Some(ref size) => Some(size.bytes()?),
+                        None => None,
+                    },
+                    constants::DEFAULT_LOG_MAX_SIZE,
+                    rotation::parse_size,
+                )?,
+                keep: r.pick(
+                    "log_rotation.keep",
+                    constants::LOG_KEEP_ENV_VAR,
+                    rotation.keep,
+                    constants::DEFAULT_LOG_KEEP,
+                    |s| Ok(usize::from_str(s)?),
+                )?,
+                compress: r.pick(
+                    "log_rotation.compress",
+                    constants::LOG_COMPRESS_ENV_VAR,
+                    rotation.compress,
+                    false,
+                    rotation::parse_bool,
+                )?,
+            },
+            color: r.pick(
+                "color",
+                constants::COLOR_ENV_VAR,
+                file.color,
+                ColorMode::Auto,
+                ColorMode::from_str,
+            )?,
+            list_columns: r.pick(
+                "list_columns",
+                constants::LIST_COLUMNS_ENV_VAR,
+                file.list_columns,
+                vec!["status".to_string(), "ports".to_string()],
+                |s| Ok(s.split(',').map(|c| c.trim().to_string()).collect()),
+            )?,
+            log_level: r.pick(
+                "log_level",
+                constants::LOG_LEVEL_ENV_VAR,
+                match file.log_level {
+                    Some(ref level) => Some(parse_level(level)?),
+                    None => None,
+                },
+                LevelFilter::Trace,
+                parse_level,
+            )?,
+            config_dir,
+            state_dir,
+            sources: r.sources,
+            flags: r.flags,
+        })
+    }
+
+    pub fn run_dir(&self) -> PathBuf {
+        self.state_dir.join("run")
+    }
+
+    // dmgr's own log, as opposed to those of its services
+    pub fn dmgr_log(&self) -> PathBuf {
+        self.state_dir.join("dmgr.log")
+    }
+
+    // Every setting as it would be written in dmgr.toml, with its source
+    pub fn describe(&self) -> Vec<(&'static str, String, Source)> {
+        let values = vec![
+            ("registry", self.registry.display().to_string()),
+            ("config_dir", self.config_dir.display().to_string()),
+            ("state_dir", self.state_dir.display().to_string()),
+            ("log_dir", self.log_dir.display().to_string()),
+            ("start_timeout", self.start_timeout.as_secs().to_string()),
+            (
+                "stop_grace_period",
+                self.stop_grace_period.as_secs().to_string(),
+            ),
+            (
+                "log_rotation.max_size",
+                self.log_rotation.max_size.to_string(),
+            ),
+            ("log_rotation.keep", self.log_rotation.keep.to_string()),
+            (
+                "log_rotation.compress",
+                self.log_rotation.compress.to_string(),
+            ),
+            ("color", self.color.as_str().to_string()),
+            ("list_columns", self.list_columns.join(",")),
+            ("log_level", self.log_level.to_string().to_lowercase()),
+        ];
+
+        values
+            .into_iter()
+            .map(|(key, value)| {
+                let source = self.sources.get(key).cloned().unwrap_or(Source::Default);
+                (key, value, source)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsFile {
+    start_timeout: Option<u64>,
+    stop_grace_period: Option<u64>,
+    log_dir: Option<PathBuf>,
+    log_rotation: Option<LogRotationContent>,
+    color: Option<ColorMode>,
+    list_columns: Option<Vec<String>>,
+    log_level: Option<String>,
+}
+
+impl SettingsFile {
+    // Everything is a default when there is no file
+    fn read(path: &PathBuf) -> DmgrResult<Self> {
+        if !path.exists() {
+            return Ok(SettingsFile::default());
+        }
+
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        toml::from_str(&contents).map_err(|e| dmgr_err!("invalid settings in {:?}: {}", path, e))
+    }
+}
+
+struct Resolver {
+    // of dmgr.toml
+    path: PathBuf,
+    flags: Vec<(&'static str, String)>,
+    sources: BTreeMap<&'static str, Source>,
+}
+
+impl Resolver {
+    // The flag or env var if set, else the value from dmgr.toml, else the
+    // default
+    fn pick<T, F>(
+        &mut self,
+        key: &'static str,
+        var: &'static str,
+        from_file: Option<T>,
+        default: T,
+        parse: F,
+    ) -> DmgrResult<T>
+    where
+        F: Fn(&str) -> DmgrResult<T>,
+    {
+        if let Some((value, source)) = given(&self.flags, var) {
+            let parsed = parse(&value).map_err(|e| dmgr_err!("invalid {}: {}", source, e))?;
+            self.sources.insert(key, source);
+            return Ok(parsed);
+        }
+
+        let (value, source) = match from_file {
+            Some(value) => (value, Source::File(self.path.clone())),
+            None => (default, Source::Default),
+        };
+        self.sources.insert(key, source);
+        Ok(value)
+    }
+}
+
+// The value for an env var from its flag, if that was given, else from the
+// environment
+fn given(flags: &[(&'static str, String)], var: &'static str) -> Option<(String, Source)> {
+    let flag = FLAGS
+        .iter()
+        .find(|f| f.1 == var)
+        .and_then(|f| flags.iter().find(|g| g.0 == f.0));
+    match flag {
+        Some(&(flag, ref value)) => Some((value.clone(), Source::Flag(flag))),
+        None => env::var(var).ok().map(|value| (value, Source::Env(var))),
+    }
+}
+
+fn xdg_dirs() -> DmgrResult<(PathBuf, PathBuf)> {
+    let home = home::home_dir().ok_or(dmgr_err!("could not determine home dir"))?;
+    let base = |var, default: &str| {
+        env::var_os(var)
+            .map(PathBuf::from)
+            // the spec says to ignore relative paths
+            .filter(|p| p.is_absolute())
+            .unwrap_or_else(|| home.join(default))
+            .join("dmgr")
+    };
+
+    Ok((
+        base("XDG_CONFIG_HOME", ".config"),
+        base("XDG_STATE_HOME", ".local/state"),
+    ))
+}
+
+fn parse_secs(s: &str) -> DmgrResult<Duration> {
+    Ok(Duration::from_secs(u64::from_str(s)?))
+}
+
+fn parse_level(s: &str) -> DmgrResult<LevelFilter> {
+    LevelFilter::from_str(s).map_err(|_| {
+        dmgr_err!(
+            "unknown log level {:?}; use off, error, warn, info, debug or trace",
+            s
+        )
+    })
+}
+
+// Flags are relative to where dmgr was run, which services don't share
+fn absolute(path: &str) -> DmgrResult<PathBuf> {
+    Ok(env::current_dir()?.join(path))
+}
@@ -0,0 +1,157 @@
+// Point-in-time status of services, probing each check only once
+extern crate serde_derive;
+
+use self::serde_derive::Serialize;
+
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use config::Pid;
+use service::Service;
+
+// In the order `list --sort status` shows them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    // running and passing all of its checks
+    #[serde(rename = "running")]
+    Ready,
+    // running but some check is not passing yet
+    Waiting,
+    Stopped,
+    // the probe did not finish in time
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub state: State,
+    // running, but not from a process dmgr started
+    pub disowned: bool,
+    // the runfile's pid, while what it recorded is running
+    pub pid: Option<Pid>,
+    pub ports: Vec<u16>,
+    pub open_ports: Vec<u16>,
+}
+
+impl State {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            State::Ready => "running",
+            State::Waiting => "waiting",
+            State::Stopped => "stopped",
+            State::Unknown => "unknown",
+        }
+    }
+}
+
+impl Snapshot {
+    fn unknown(svc: &Service) -> Self {
+        Snapshot {
+            name: svc.name.clone(),
+            state: State::Unknown,
+            disowned: false,
+            pid: None,
+            ports: svc.ports.clone(),
+            open_ports: vec![],
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == State::Ready || self.state == State::Waiting
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state == State::Ready
+    }
+
+    // As shown by `list`; a trailing * marks a disowned service
+    pub fn status(&self) -> String {
+        let status = match self.state {
+            State::Ready => "running",
+            State::Waiting => "waiting",
+            State::Stopped => "-",
+            State::Unknown => "?",
+        };
+
+        if self.disowned {
+            format!("{}*", status)
+        } else {
+            status.to_string()
+        }
+    }
+}
+
+// The http and health checks only run once the service is known to be
+// running, as they can't pass otherwise
+pub fn probe(svc: &Service, system: &sysinfo::System) -> Snapshot {
+    let runfile = svc.live_runfile_in(system);
+    let open_ports = svc.open_ports();
+    let running = runfile.is_some() || !open_ports.is_empty();
+
+    let waiting = running
+        && ((!svc.ports.is_empty() && open_ports.is_empty())
+            || !svc.http_check_passing()
+            || svc.has_health_check_failing());
+
+    let state = match (running, waiting) {
+        (false, _) => State::Stopped,
+        (true, true) => State::Waiting,
+        (true, false) => State::Ready,
+    };
+
+    Snapshot {
+        name: svc.name.clone(),
+        state,
+        disowned: runfile.is_none() && !open_ports.is_empty(),
+        pid: runfile.and_then(|r| r.pid),
+        ports: svc.ports.clone(),
+        open_ports,
+    }
+}
+
+// Probes every service at once, sharing one process table between them.
+// Services whose probe hasn't finished by the timeout come back as Unknown.
+pub fn probe_all(
+    services: &[Service],
+    system: Arc<sysinfo::System>,
+    timeout: Duration,
+) -> Vec<Snapshot> {
+    let deadline = Instant::now() + timeout;
+    let (tx, rx) = mpsc::channel();
+
+    for (i, svc) in services.iter().cloned().enumerate() {
+        let (tx, system) = (tx.clone(), system.clone());
+        thread::spawn(move || {
+            // the receiver is gone once the deadline has passed
+            let _ = tx.send((i, probe(&svc, &system)));
+        });
+    }
+    drop(tx);
+
+    let mut snapshots: Vec<Option<Snapshot>> = vec![None; services.len()];
+    let mut remaining = services.len();
+    while remaining > 0 {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        match rx.recv_timeout(deadline - now) {
+            Ok((i, snapshot)) => {
+                snapshots[i] = Some(snapshot);
+                remaining -= 1;
+            }
+            Err(_) => break,
+        }
+    }
+
+    services
+        .iter()
+        .zip(snapshots)
+        .map(|(svc, s)| s.unwrap_or_else(|| Snapshot::unknown(svc)))
+        .collect()
+}
@@ -0,0 +1,48 @@
+use prettytable::format;
+use prettytable::Table;
+use prettytable::*;
+
+pub struct TableBuilder {
+    pub table: Table,
+    rows: Vec<Vec<String>>,
+}
+
+impl TableBuilder {
+    pub fn new() -> TableBuilder {
+        let mut table = Table::new();
+        let format = format::FormatBuilder::new()
+            .column_separator(' ')
+            .separator(
+                format::LinePosition::Title,
+                format::LineSeparator::new('-', ' ', ' ', ' '),
+            )
+            .padding(0, 1)
+            .build();
+        table.set_format(format);
+
+        TableBuilder {
+            table,
+            rows: vec![],
+        }
+    }
+
+    pub fn header<T>(mut self, header: Vec<T>) -> Self
+    where
+        T: AsRef<str> + std::fmt::Display,
+    {
+        self.table.set_titles(Row::from(header));
+        self
+    }
+
+    pub fn add_row<T>(mut self, row: Vec<T>) -> Self
+    where
+        T: AsRef<str> + std::fmt::Display,
+    {
+        self.table.add_row(Row::from(row));
+        self
+    }
+
+    pub fn build(self) -> Table {
+        self.table
+    }
+}
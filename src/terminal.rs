@@ -0,0 +1,94 @@
+// Drives an interactive terminal: raw keyboard input and full-screen redraws
+extern crate libc;
+
+use std::io;
+use std::io::{Read, Write};
+use std::mem;
+
+use command::DmgrResult;
+use logging;
+
+pub const CLEAR: &str = "\x1b[2J\x1b[H";
+const ENTER_ALT_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE_ALT_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Char(char),
+    // Ctrl-C, which no longer raises SIGINT in raw mode
+    Interrupt,
+}
+
+// Puts the terminal in raw mode on an alternate screen until dropped, so the
+// shell's screen and settings come back however dmgr exits the loop. Console
+// logging is off meanwhile, as it would write over the screen.
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub fn enable() -> DmgrResult<Self> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            fail!("an interactive session needs a terminal")
+        }
+
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        // reads return after a tenth of a second even without a key, so the
+        // caller can keep redrawing while it waits
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 1;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        logging::set_console(false);
+        print!("{}", ENTER_ALT_SCREEN);
+        io::stdout().flush()?;
+        Ok(RawMode { original })
+    }
+
+    // The next key pressed, or None if there wasn't one within the read timeout
+    pub fn read_key(&self) -> DmgrResult<Option<Key>> {
+        let mut buf = [0; 3];
+        let n = io::stdin().read(&mut buf)?;
+
+        let key = match &buf[..n] {
+            [] => None,
+            [3] => Some(Key::Interrupt),
+            [0x1b, b'[', b'A'] => Some(Key::Up),
+            [0x1b, b'[', b'B'] => Some(Key::Down),
+            [c] if c.is_ascii() => Some(Key::Char(*c as char)),
+            _ => None,
+        };
+        Ok(key)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        print!("{}", LEAVE_ALT_SCREEN);
+        let _ = io::stdout().flush();
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+        logging::set_console(true);
+    }
+}
+
+// Columns and rows of the terminal, if stdout is one
+pub fn size() -> Option<(usize, usize)> {
+    let mut ws: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } != 0 || ws.ws_col == 0
+    {
+        return None;
+    }
+    Some((ws.ws_col as usize, ws.ws_row as usize))
+}
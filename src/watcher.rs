@@ -0,0 +1,142 @@
+// Polls a directory tree for file changes
+extern crate glob;
+
+use self::glob::Pattern;
+
+use std::collections::btree_map::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use command::DmgrResult;
+use config::WatchConfigContent;
+use constants;
+
+pub struct Watcher {
+    root: PathBuf,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    interval: Duration,
+    mtimes: BTreeMap<PathBuf, SystemTime>,
+}
+
+impl Watcher {
+    pub fn new(root: &PathBuf, cfg: &WatchConfigContent) -> DmgrResult<Self> {
+        let include = match cfg.include {
+            Some(ref globs) => globs.clone(),
+            None => vec![String::from("**/*")],
+        };
+        let exclude = match cfg.exclude {
+            Some(ref globs) => globs.clone(),
+            None => constants::DEFAULT_WATCH_EXCLUDES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        };
+        let interval = cfg
+            .interval_ms
+            .unwrap_or(constants::DEFAULT_WATCH_INTERVAL_MILLIS);
+
+        let mut watcher = Watcher {
+            root: root.clone(),
+            include: patterns(&include)?,
+            exclude: patterns(&exclude)?,
+            interval: Duration::from_millis(interval),
+            mtimes: BTreeMap::new(),
+        };
+        watcher.mtimes = watcher.scan();
+
+        Ok(watcher)
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    // Returns every watched file created, modified or deleted since the last call
+    pub fn changes(&mut self) -> DmgrResult<Vec<PathBuf>> {
+        let current = self.scan();
+
+        let mut changed: Vec<PathBuf> = current
+            .iter()
+            .filter(|&(path, mtime)| self.mtimes.get(path) != Some(mtime))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            self.mtimes
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .cloned(),
+        );
+
+        self.mtimes = current;
+        Ok(changed)
+    }
+
+    fn scan(&self) -> BTreeMap<PathBuf, SystemTime> {
+        let mut mtimes = BTreeMap::new();
+        self.scan_dir(&self.root, &mut mtimes);
+        mtimes
+    }
+
+    // Files can disappear mid-scan, so unreadable entries are skipped rather
+    // than failing the whole scan
+    fn scan_dir(&self, dir: &Path, mtimes: &mut BTreeMap<PathBuf, SystemTime>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let rel_path = match path.strip_prefix(&self.root) {
+                Ok(p) => p.to_path_buf(),
+                Err(_) => continue,
+            };
+
+            if self.exclude.iter().any(|p| p.matches_path(&rel_path)) {
+                continue;
+            }
+
+            let file_type = match entry.file_type() {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+
+            if file_type.is_dir() {
+                self.scan_dir(&path, mtimes);
+            } else if self.include.iter().any(|p| p.matches_path(&rel_path)) {
+                if let Ok(mtime) = entry.metadata().and_then(|m| m.modified()) {
+                    mtimes.insert(path, mtime);
+                }
+            }
+        }
+    }
+}
+
+fn patterns(globs: &[String]) -> DmgrResult<Vec<Pattern>> {
+    globs
+        .iter()
+        .map(|g| Pattern::new(g).map_err(|e| dmgr_err!("invalid watch glob {:?}: {}", g, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scratch::ScratchDir;
+
+    #[test]
+    fn skips_excluded_dirs_at_any_depth() {
+        let root = ScratchDir::new("watcher");
+        for dir in &["src", "node_modules/a", "web/node_modules/b", "web/.git"] {
+            root.write(Path::new(dir).join("f"), "");
+        }
+
+        let watcher = Watcher::new(root.path(), &WatchConfigContent::default()).unwrap();
+        let watched: Vec<PathBuf> = watcher.mtimes.keys().cloned().collect();
+        assert_eq!(watched, vec![root.join("src/f")]);
+    }
+}
@@ -0,0 +1,80 @@
+// Runs the dmgr binary against a scratch home dir
+extern crate serde_json;
+extern crate serde_yaml;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+struct Home {
+    dir: PathBuf,
+}
+
+impl Home {
+    fn new(test: &str) -> Self {
+        let dir = env::temp_dir().join(format!("dmgr-cli-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Home { dir }
+    }
+
+    // A registry with a service whose repo, and so its config, is gone
+    fn with_broken_service(self) -> Self {
+        let registry = r#"{
+  "schema_version": 2,
+  "services": { "gone": { "repo_path": "/nonexistent/gone" } },
+  "groups": {}
+}"#;
+        fs::write(self.dir.join("service-registry.json"), registry).unwrap();
+        self
+    }
+
+    fn dmgr(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_dmgr"))
+            .arg("--home")
+            .arg(&self.dir)
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn warned_about_gone(out: &Output) -> bool {
+    String::from_utf8_lossy(&out.stderr).contains("could not load \"gone\"")
+}
+
+#[test]
+fn health_json_is_only_json_with_a_broken_service() {
+    let home = Home::new("health").with_broken_service();
+    let out = home.dmgr(&["health", "--all", "--json"]);
+
+    let health: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(health["healthy"], true);
+    assert_eq!(health["services"], serde_json::json!([]));
+    assert!(warned_about_gone(&out));
+    assert!(out.status.success());
+}
+
+#[test]
+fn list_output_is_only_the_listing_with_a_broken_service() {
+    let home = Home::new("list").with_broken_service();
+
+    let out = home.dmgr(&["list", "-o", "json"]);
+    let listed: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(listed, serde_json::json!([]));
+    assert!(warned_about_gone(&out));
+
+    let out = home.dmgr(&["list", "-o", "yaml"]);
+    let listed: serde_yaml::Value = serde_yaml::from_slice(&out.stdout).unwrap();
+    assert_eq!(listed, serde_yaml::Value::Sequence(vec![]));
+
+    let out = home.dmgr(&["list", "-o", "tsv"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout).lines().count(), 1);
+}